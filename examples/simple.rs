@@ -8,6 +8,7 @@ struct Person {
     active: bool,
 }
 
+#[allow(clippy::bool_assert_comparison)]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create a simple person struct
     let person = Person {
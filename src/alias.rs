@@ -0,0 +1,375 @@
+//! Key dictionaries that replace long paths and segments with short aliases.
+//!
+//! DNS-SD recommends keys of nine bytes or fewer, and a nested path such as
+//! `services.database.host` uses up much of a 255 byte record. A [`KeyDictionary`] maps paths
+//! to aliases when writing and expands them again when reading. The dictionary can be given
+//! up front, or derived from the keys of each value and published as a record of its own.
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_txtrecord::{KeyDictionary, TxtRecordConfig};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Database {
+//!     host: String,
+//!     replicas: Vec<String>,
+//! }
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Services {
+//!     database: Database,
+//! }
+//!
+//! let services = Services {
+//!     database: Database { host: "db".to_string(), replicas: vec!["r1".to_string()] },
+//! };
+//!
+//! let config = TxtRecordConfig::builder()
+//!     .key_dictionary(KeyDictionary::new([("database", "d"), ("replicas", "r")]))
+//!     .build()
+//!     .unwrap();
+//! let records = serde_txtrecord::to_txt_records_with_config(&services, config.clone()).unwrap();
+//! assert_eq!(records.get("d.host"), Some("db"));
+//! assert_eq!(records.get("d.r_0"), Some("r1"));
+//! assert_eq!(records.get("d.r_len"), Some("1"));
+//!
+//! let decoded: Services = serde_txtrecord::from_txt_records_with_config(records, config).unwrap();
+//! assert_eq!(decoded, services);
+//!
+//! // a derived dictionary is published with the records and required to read them
+//! let config = TxtRecordConfig::builder()
+//!     .key_dictionary(KeyDictionary::derived("dict"))
+//!     .build()
+//!     .unwrap();
+//! let records = serde_txtrecord::to_txt_records_with_config(&services, config.clone()).unwrap();
+//! assert_eq!(records.get("dict"), Some("a=database,b=replicas,c=host"));
+//! assert_eq!(records.get("a.b_0"), Some("r1"));
+//!
+//! let decoded: Services = serde_txtrecord::from_txt_records_with_config(records, config).unwrap();
+//! assert_eq!(decoded, services);
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{ConfigError, DeserializeError, TxtRecordConfig, TxtRecords};
+
+/// Aliases for record key paths, see the [module documentation](self)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyDictionary {
+    /// Alias of each path, where a path is one or more object fields joined by the
+    /// [`object_separator`](TxtRecordConfig::object_separator), e.g. `services.database`
+    ///
+    /// Array indices and length suffixes are kept after the alias, so `replicas` also
+    /// shortens `replicas_0` and `replicas_len`.
+    pub aliases: BTreeMap<String, String>,
+    /// Derive an alias for every field that is longer than its alias when serializing,
+    /// replacing [`aliases`](Self::aliases)
+    pub derive: bool,
+    /// Key of the record the dictionary is published in (default: none)
+    ///
+    /// When set, the serializer writes the dictionary to this record and the deserializer
+    /// reads the aliases from it, failing if the record is missing.
+    pub record: Option<String>,
+}
+
+impl KeyDictionary {
+    /// Create a dictionary from `(path, alias)` pairs
+    pub fn new<I, P, A>(aliases: I) -> Self
+    where
+        I: IntoIterator<Item = (P, A)>,
+        P: Into<String>,
+        A: Into<String>,
+    {
+        Self {
+            aliases: aliases
+                .into_iter()
+                .map(|(path, alias)| (path.into(), alias.into()))
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Create a dictionary that is derived from the keys of each value and published in
+    /// `record`
+    pub fn derived(record: impl Into<String>) -> Self {
+        Self {
+            derive: true,
+            record: Some(record.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Publish the dictionary in `record`
+    pub fn published(mut self, record: impl Into<String>) -> Self {
+        self.record = Some(record.into());
+        self
+    }
+
+    /// Check that aliased keys can be expanded again
+    pub(crate) fn validate(&self, config: &TxtRecordConfig) -> Result<(), ConfigError> {
+        if self.derive && self.record.is_none() {
+            return Err(ConfigError::UnpublishedKeyDictionary);
+        }
+        if self.record.as_deref() == Some("") {
+            return Err(ConfigError::EmptyKeyDictionaryRecord);
+        }
+
+        let mut seen = HashSet::new();
+        for (path, alias) in &self.aliases {
+            let invalid = path.is_empty()
+                || alias.is_empty()
+                || [path, alias].iter().any(|part| part.contains([',', '=']))
+                || alias.contains(config.object_separator.as_str())
+                || split_suffix(alias, &config.array_separator, &config.array_len_suffix).0
+                    != alias;
+            if invalid {
+                return Err(ConfigError::InvalidAlias {
+                    path: path.clone(),
+                    alias: alias.clone(),
+                });
+            }
+            if !seen.insert(config.key_case.apply(alias)) {
+                return Err(ConfigError::DuplicateAlias(alias.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Shorten a key, returning the field that collides with an alias on failure
+    pub(crate) fn encode(&self, key: &str, config: &TxtRecordConfig) -> Result<String, String> {
+        if key.is_empty() || self.aliases.is_empty() {
+            return Ok(key.to_string());
+        }
+        let separator = config.object_separator.as_str();
+        let aliases: HashSet<_> = self
+            .aliases
+            .values()
+            .map(|alias| config.key_case.apply(alias))
+            .collect();
+
+        let segments: Vec<&str> = key.split(separator).collect();
+        let mut encoded = Vec::with_capacity(segments.len());
+        let mut start = 0;
+        'segments: while start < segments.len() {
+            // the longest path wins, and only its last field may carry an index or suffix
+            for end in (start + 1..=segments.len()).rev() {
+                let (base, suffix) = split_suffix(
+                    segments[end - 1],
+                    &config.array_separator,
+                    &config.array_len_suffix,
+                );
+                let mut path = segments[start..end - 1].join(separator);
+                if end - 1 > start {
+                    path.push_str(separator);
+                }
+                path.push_str(base);
+                if let Some(alias) = self.aliases.get(&path) {
+                    encoded.push(format!("{}{}", alias, suffix));
+                    start = end;
+                    continue 'segments;
+                }
+            }
+
+            let (base, _) = split_suffix(
+                segments[start],
+                &config.array_separator,
+                &config.array_len_suffix,
+            );
+            if aliases.contains(&config.key_case.apply(base)) {
+                return Err(base.to_string());
+            }
+            encoded.push(segments[start].to_string());
+            start += 1;
+        }
+        Ok(encoded.join(separator))
+    }
+
+    /// Returns the full key of the dictionary record, with the prefix and key case applied
+    pub(crate) fn record_key(&self, config: &TxtRecordConfig) -> Option<String> {
+        let record = self.record.as_deref()?;
+        let key = match &config.prefix {
+            Some(prefix) => format!("{}{}{}", prefix, config.object_separator, record),
+            None => record.to_string(),
+        };
+        Some(config.key_case.apply(&key).into_owned())
+    }
+
+    /// Returns the value of the dictionary record
+    pub(crate) fn to_record_value(&self) -> String {
+        let mut entries: Vec<_> = self
+            .aliases
+            .iter()
+            .map(|(path, alias)| (alias, path))
+            .collect();
+        entries.sort();
+        entries
+            .into_iter()
+            .map(|(alias, path)| format!("{}={}", alias, path))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn from_record_value(record: &str, value: &str) -> Result<Self, DeserializeError> {
+        let aliases = value
+            .split(',')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .split_once('=')
+                    .map(|(alias, path)| (path.to_string(), alias.to_string()))
+                    .ok_or_else(|| {
+                        DeserializeError::InvalidFormat(format!(
+                            "key dictionary {} has no '=' in entry {}",
+                            record, entry
+                        ))
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            aliases,
+            derive: false,
+            record: Some(record.to_string()),
+        })
+    }
+}
+
+/// Expands aliased keys, with the key case already applied to the keys
+pub(crate) struct KeyExpander {
+    paths: HashMap<String, String>,
+    separator: String,
+    array_separator: String,
+    array_len_suffix: String,
+}
+
+impl KeyExpander {
+    pub(crate) fn new(dictionary: &KeyDictionary, config: &TxtRecordConfig) -> Self {
+        let case = config.key_case;
+        Self {
+            paths: dictionary
+                .aliases
+                .iter()
+                .map(|(path, alias)| {
+                    (
+                        case.apply(alias).into_owned(),
+                        case.apply(path).into_owned(),
+                    )
+                })
+                .collect(),
+            separator: case.apply(&config.object_separator).into_owned(),
+            array_separator: case.apply(&config.array_separator).into_owned(),
+            array_len_suffix: case.apply(&config.array_len_suffix).into_owned(),
+        }
+    }
+
+    pub(crate) fn expand(&self, key: &str) -> String {
+        if self.paths.is_empty() {
+            return key.to_string();
+        }
+        key.split(self.separator.as_str())
+            .map(|segment| {
+                let (base, suffix) =
+                    split_suffix(segment, &self.array_separator, &self.array_len_suffix);
+                match self.paths.get(base) {
+                    Some(path) => format!("{}{}", path, suffix),
+                    None => segment.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(&self.separator)
+    }
+}
+
+/// Derive aliases for the fields of `keys`, the unaliased keys of one value
+///
+/// Fields that save the most bytes get the shortest aliases, and aliases never equal a field.
+pub(crate) fn derive_aliases<'k>(
+    keys: impl Iterator<Item = &'k str>,
+    config: &TxtRecordConfig,
+) -> BTreeMap<String, String> {
+    let mut fields: HashMap<&str, usize> = HashMap::new();
+    for key in keys {
+        for segment in key.split(config.object_separator.as_str()) {
+            let (base, _) =
+                split_suffix(segment, &config.array_separator, &config.array_len_suffix);
+            if !base.is_empty() {
+                *fields.entry(base).or_default() += 1;
+            }
+        }
+    }
+    let taken: HashSet<_> = fields
+        .keys()
+        .map(|field| config.key_case.apply(field))
+        .collect();
+
+    let mut fields: Vec<(&str, usize)> = fields.into_iter().collect();
+    fields.sort_by(|(a, a_count), (b, b_count)| {
+        (b.len() * b_count)
+            .cmp(&(a.len() * a_count))
+            .then_with(|| a.cmp(b))
+    });
+
+    let mut candidates = (1..)
+        .flat_map(aliases_of_len)
+        .filter(|alias| {
+            !taken.contains(&config.key_case.apply(alias))
+                && !alias.contains(config.object_separator.as_str())
+        })
+        .peekable();
+    let mut aliases = BTreeMap::new();
+    for (field, _) in fields {
+        let alias = candidates.peek().expect("aliases are unbounded");
+        if alias.len() < field.len() {
+            aliases.insert(field.to_string(), candidates.next().expect("peeked"));
+        }
+    }
+    aliases
+}
+
+/// Read the dictionary published in the records, if the configuration has one
+pub(crate) fn resolve(
+    records: &TxtRecords,
+    config: &TxtRecordConfig,
+) -> Result<Option<KeyDictionary>, DeserializeError> {
+    let Some(dictionary) = &config.key_dictionary else {
+        return Ok(None);
+    };
+    let (Some(record), Some(key)) = (&dictionary.record, dictionary.record_key(config)) else {
+        return Ok(Some(dictionary.clone()));
+    };
+    let value = records
+        .iter()
+        .find(|(record_key, _)| config.key_case.apply(record_key) == key)
+        .map(|(_, value)| value)
+        .ok_or_else(|| DeserializeError::MissingKeyDictionary(record.clone()))?;
+    KeyDictionary::from_record_value(record, value).map(Some)
+}
+
+/// Lowercase aliases of `len` letters in order: `a` to `z`, then `aa` to `zz`, and so on
+fn aliases_of_len(len: u32) -> impl Iterator<Item = String> {
+    (0..26usize.pow(len)).map(move |mut n| {
+        let mut alias = vec![b'a'; len as usize];
+        for c in alias.iter_mut().rev() {
+            *c = b'a' + (n % 26) as u8;
+            n /= 26;
+        }
+        String::from_utf8(alias).expect("letters are ASCII")
+    })
+}
+
+/// Split a field from its array indices and length suffix, e.g. `tags_0_len` into `tags` and
+/// `_0_len`
+fn split_suffix<'s>(
+    segment: &'s str,
+    array_separator: &str,
+    array_len_suffix: &str,
+) -> (&'s str, &'s str) {
+    let mut base = segment.strip_suffix(array_len_suffix).unwrap_or(segment);
+    while let Some(pos) = base.rfind(array_separator) {
+        let index = &base[pos + array_separator.len()..];
+        if index.is_empty() || !index.bytes().all(|c| c.is_ascii_digit()) {
+            break;
+        }
+        base = &base[..pos];
+    }
+    (base, &segment[base.len()..])
+}
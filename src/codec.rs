@@ -0,0 +1,443 @@
+//! Key codecs that control how the path of a value is laid out in its record key.
+//!
+//! A path is made of [`Segment`]s: struct fields, map keys and array indices. A [`KeyCodec`]
+//! joins a segment onto the key of its parent when writing, and splits the members of an
+//! object back out of the record keys when reading. [`TxtRecordConfig`] is the default codec,
+//! which joins fields and map keys with the `object_separator`, indices with the
+//! `array_separator`, and names array lengths with the `array_len_suffix`.
+//!
+//! A custom codec is set with [`TxtRecordConfig::key_codec`]. This module provides
+//! [`BracketCodec`] for `authors[0][name]`, [`ZeroPaddedCodec`] for indices of a fixed width
+//! such as `authors_007.name`, and [`EscapingCodec`] for map keys that contain the
+//! `object_separator`, such as host names. The record set helpers, such as
+//! [`describe`](crate::describe), [`size_report`](crate::size_report) and
+//! [`merge_layers`](crate::layered::merge_layers), follow the codec. Key dictionaries rewrite
+//! keys themselves and bracket keys in query strings are written by [`BracketCodec`], so both
+//! fail with [`ConfigError::ConflictingKeyCodec`](crate::ConfigError::ConflictingKeyCodec)
+//! when a codec is set.
+//!
+//! A codec of your own implements [`KeyCodec`]:
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_txtrecord::codec::{KeyCodec, Segment};
+//! use serde_txtrecord::TxtRecordConfig;
+//! use std::borrow::Cow;
+//!
+//! /// `servers[00].name`, with zero-padded indices in brackets
+//! #[derive(Debug)]
+//! struct Brackets;
+//!
+//! impl KeyCodec for Brackets {
+//!     fn join(&self, base: &str, segment: Segment<'_>) -> String {
+//!         match segment {
+//!             Segment::Field(name) | Segment::MapKey(name) if base.is_empty() => name.to_string(),
+//!             Segment::Field(name) | Segment::MapKey(name) => format!("{}.{}", base, name),
+//!             Segment::Index(index) => format!("{}[{:02}]", base, index),
+//!         }
+//!     }
+//!
+//!     fn len_key(&self, base: &str) -> String {
+//!         format!("{}[]", base)
+//!     }
+//!
+//!     fn split_member<'k>(
+//!         &self,
+//!         base: &str,
+//!         key: &'k str,
+//!         _exists: &dyn Fn(&str) -> bool,
+//!     ) -> Option<Cow<'k, str>> {
+//!         let rest = if base.is_empty() { key } else { key.strip_prefix(base)?.strip_prefix('.')? };
+//!         let end = rest.find(['.', '[']).unwrap_or(rest.len());
+//!         (end > 0).then(|| Cow::Borrowed(&rest[..end]))
+//!     }
+//! }
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Server {
+//!     name: String,
+//! }
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Cluster {
+//!     servers: Vec<Server>,
+//! }
+//!
+//! let cluster = Cluster { servers: vec![Server { name: "a".to_string() }] };
+//! let config = TxtRecordConfig::builder().key_codec(Brackets).build().unwrap();
+//!
+//! let records = serde_txtrecord::to_txt_records_with_config(&cluster, config.clone()).unwrap();
+//! assert_eq!(records.get("servers[00].name"), Some("a"));
+//! assert_eq!(records.get("servers[]"), Some("1"));
+//!
+//! let decoded: Cluster = serde_txtrecord::from_txt_records_with_config(records, config).unwrap();
+//! assert_eq!(decoded, cluster);
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::TxtRecordConfig;
+
+/// A step in the path of a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    /// A struct field
+    Field(&'a str),
+    /// The key of a map entry, which may contain any character
+    MapKey(&'a str),
+    /// An array or tuple element
+    Index(usize),
+}
+
+/// Lays out the path of a value in its record key, see the [module documentation](self)
+///
+/// Keys are passed to the codec before the [`prefix`](TxtRecordConfig::prefix) and
+/// [`key_case`](TxtRecordConfig::key_case) are applied, except that the record keys passed
+/// to [`split_member`](Self::split_member) and the `base` they are split from have the key
+/// case applied.
+pub trait KeyCodec: fmt::Debug + Send + Sync {
+    /// Append a segment to the key of its parent, where `base` is empty at the root
+    fn join(&self, base: &str, segment: Segment<'_>) -> String;
+
+    /// Returns the key of the length record of the array at `base`
+    fn len_key(&self, base: &str) -> String;
+
+    /// Returns the name of the member of the object at `base` that a record key belongs to,
+    /// or `None` if the record is not below `base`
+    ///
+    /// The name is the unescaped field name or map key that [`join`](Self::join) was given.
+    /// `exists` tells whether a record key exists, for layouts that cannot tell an index
+    /// from part of a name without looking for the length record of the array.
+    fn split_member<'k>(
+        &self,
+        base: &str,
+        key: &'k str,
+        exists: &dyn Fn(&str) -> bool,
+    ) -> Option<Cow<'k, str>>;
+}
+
+impl KeyCodec for TxtRecordConfig {
+    fn join(&self, base: &str, segment: Segment<'_>) -> String {
+        match segment {
+            Segment::Field(name) | Segment::MapKey(name) if base.is_empty() => name.to_string(),
+            Segment::Field(name) | Segment::MapKey(name) => {
+                format!("{}{}{}", base, self.object_separator, name)
+            }
+            Segment::Index(index) => format!("{}{}{}", base, self.array_separator, index),
+        }
+    }
+
+    fn len_key(&self, base: &str) -> String {
+        format!("{}{}", base, self.array_len_suffix)
+    }
+
+    fn split_member<'k>(
+        &self,
+        base: &str,
+        key: &'k str,
+        exists: &dyn Fn(&str) -> bool,
+    ) -> Option<Cow<'k, str>> {
+        let object_separator = self.key_case.apply(&self.object_separator);
+        let prefix = if base.is_empty() {
+            String::new()
+        } else {
+            format!("{}{}", base, object_separator)
+        };
+        let suffix = key.strip_prefix(prefix.as_str())?;
+
+        // the first segment below the prefix names a member, unless it is an array element
+        // or length record, which belong to the array member instead
+        let segment = match suffix.find(object_separator.as_ref()) {
+            Some(pos) => &suffix[..pos],
+            None => suffix,
+        };
+        if segment.is_empty() {
+            return None;
+        }
+        Some(Cow::Borrowed(self.member_name(&prefix, segment, exists)))
+    }
+}
+
+impl TxtRecordConfig {
+    /// Returns the configured [`key_codec`](Self::key_codec), or the separators of this
+    /// configuration
+    pub(crate) fn codec(&self) -> &dyn KeyCodec {
+        match &self.key_codec {
+            Some(codec) => codec.as_ref(),
+            None => self,
+        }
+    }
+
+    /// Returns the member named by a key segment, stripping array indices (`tags_0`) and
+    /// length suffixes (`tags_len`) when the segment belongs to an array.
+    ///
+    /// Arrays are recognized by their length record, so that names such as
+    /// `publication_year` are not mistaken for array elements.
+    fn member_name<'k>(
+        &self,
+        prefix: &str,
+        segment: &'k str,
+        exists: &dyn Fn(&str) -> bool,
+    ) -> &'k str {
+        let is_array = |base: &str| {
+            let len_key = format!("{}{}{}", prefix, base, self.array_len_suffix);
+            exists(&self.key_case.apply(&len_key))
+        };
+
+        let separator = self.key_case.apply(&self.array_separator);
+        let mut offset = 0;
+        while let Some(pos) = segment[offset..].find(separator.as_ref()) {
+            let pos = offset + pos;
+            let index = &segment[pos + separator.len()..];
+            if index.starts_with(|c: char| c.is_ascii_digit()) && is_array(&segment[..pos]) {
+                return &segment[..pos];
+            }
+            offset = pos + separator.len();
+        }
+
+        let array_len_suffix = self.key_case.apply(&self.array_len_suffix);
+        match segment.strip_suffix(array_len_suffix.as_ref()) {
+            Some(base) if !base.is_empty() && is_array(base) => base,
+            _ => segment,
+        }
+    }
+}
+
+/// Writes keys in bracket notation, e.g. `authors[0][name]`, with the length of an array in
+/// `authors[len]`
+///
+/// Names must not contain `[` or `]`. A key such as `range[len]` is the length of an array
+/// if the array has an element `range[0]`, and a field named `len` otherwise, so an empty
+/// array reads like an object with a single `len` field where the type does not tell.
+///
+/// ```rust
+/// use serde_txtrecord::codec::BracketCodec;
+/// use serde_txtrecord::TxtRecordConfig;
+/// use std::collections::BTreeMap;
+///
+/// let config = TxtRecordConfig::builder().key_codec(BracketCodec).build().unwrap();
+/// let hosts = BTreeMap::from([("api", vec![443])]);
+///
+/// let records = serde_txtrecord::to_txt_records_with_config(&hosts, config).unwrap();
+/// assert_eq!(records.get("api[0]"), Some("443"));
+/// assert_eq!(records.get("api[len]"), Some("1"));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BracketCodec;
+
+impl KeyCodec for BracketCodec {
+    fn join(&self, base: &str, segment: Segment<'_>) -> String {
+        match segment {
+            Segment::Field(name) | Segment::MapKey(name) if base.is_empty() => name.to_string(),
+            Segment::Field(name) | Segment::MapKey(name) => format!("{}[{}]", base, name),
+            Segment::Index(index) => format!("{}[{}]", base, index),
+        }
+    }
+
+    fn len_key(&self, base: &str) -> String {
+        format!("{}[len]", base)
+    }
+
+    fn split_member<'k>(
+        &self,
+        base: &str,
+        key: &'k str,
+        exists: &dyn Fn(&str) -> bool,
+    ) -> Option<Cow<'k, str>> {
+        if base.is_empty() {
+            let end = key.find('[').unwrap_or(key.len());
+            return (end > 0).then(|| Cow::Borrowed(&key[..end]));
+        }
+
+        let rest = key.strip_prefix(base)?.strip_prefix('[')?;
+        let end = rest.find(']')?;
+        let name = &rest[..end];
+        // the key case may have been applied to `len`, but never to the digits of an element
+        let is_len = name.eq_ignore_ascii_case("len") && end + 1 == rest.len();
+        if name.is_empty() || (is_len && exists(&format!("{}[0]", base))) {
+            return None;
+        }
+        Some(Cow::Borrowed(name))
+    }
+}
+
+/// Writes array indices with at least `width` digits, e.g. `authors_007.name`, so that
+/// sorting the keys as text keeps the elements in order
+///
+/// Fields, map keys and lengths are laid out with the separators, length suffix and key case
+/// of the configuration given to [`new`](Self::new).
+///
+/// ```rust
+/// use serde_txtrecord::codec::ZeroPaddedCodec;
+/// use serde_txtrecord::TxtRecordConfig;
+///
+/// let codec = ZeroPaddedCodec::new(3, &TxtRecordConfig::default());
+/// let config = TxtRecordConfig::builder().key_codec(codec).build().unwrap();
+///
+/// let records = serde_txtrecord::to_txt_records_with_config(&vec!["a", "b"], config.clone()).unwrap();
+/// assert_eq!(records.get("_001"), Some("b"));
+///
+/// let decoded: Vec<String> = serde_txtrecord::from_txt_records_with_config(records, config).unwrap();
+/// assert_eq!(decoded, ["a", "b"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ZeroPaddedCodec {
+    width: usize,
+    separators: TxtRecordConfig,
+}
+
+impl ZeroPaddedCodec {
+    /// Pad indices to `width` digits, using the separators of `config`
+    pub fn new(width: usize, config: &TxtRecordConfig) -> Self {
+        Self {
+            width,
+            separators: separators(config),
+        }
+    }
+}
+
+impl KeyCodec for ZeroPaddedCodec {
+    fn join(&self, base: &str, segment: Segment<'_>) -> String {
+        match segment {
+            Segment::Index(index) => format!(
+                "{}{}{:0width$}",
+                base,
+                self.separators.array_separator,
+                index,
+                width = self.width
+            ),
+            segment => self.separators.join(base, segment),
+        }
+    }
+
+    fn len_key(&self, base: &str) -> String {
+        self.separators.len_key(base)
+    }
+
+    fn split_member<'k>(
+        &self,
+        base: &str,
+        key: &'k str,
+        exists: &dyn Fn(&str) -> bool,
+    ) -> Option<Cow<'k, str>> {
+        self.separators.split_member(base, key, exists)
+    }
+}
+
+/// Percent-encodes `%` and the `object_separator` in field names and map keys, so that a map
+/// keyed by host names writes `hosts.example%2Ecom.port` instead of a key that reads back as
+/// nested objects
+///
+/// Keys are otherwise laid out with the separators, length suffix and key case of the
+/// configuration given to [`new`](Self::new). An `array_separator` in a name is told apart
+/// from an index by the length record of the array, as without a codec.
+///
+/// ```rust
+/// use serde_txtrecord::codec::EscapingCodec;
+/// use serde_txtrecord::TxtRecordConfig;
+/// use std::collections::BTreeMap;
+///
+/// let codec = EscapingCodec::new(&TxtRecordConfig::default());
+/// let config = TxtRecordConfig::builder().key_codec(codec).build().unwrap();
+/// let ports = BTreeMap::from([("example.com".to_string(), 443)]);
+///
+/// let records = serde_txtrecord::to_txt_records_with_config(&ports, config.clone()).unwrap();
+/// assert_eq!(records.get("example%2Ecom"), Some("443"));
+///
+/// let decoded: BTreeMap<String, u16> =
+///     serde_txtrecord::from_txt_records_with_config(records, config).unwrap();
+/// assert_eq!(decoded, ports);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EscapingCodec {
+    separators: TxtRecordConfig,
+}
+
+impl EscapingCodec {
+    /// Escape names using the separators of `config`
+    pub fn new(config: &TxtRecordConfig) -> Self {
+        Self {
+            separators: separators(config),
+        }
+    }
+
+    fn escape(&self, name: &str) -> String {
+        let separator = &self.separators.object_separator;
+        let mut escaped = String::with_capacity(name.len());
+        for c in name.chars() {
+            if c == '%' || separator.contains(c) {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    escaped.push_str(&format!("%{:02X}", byte));
+                }
+            } else {
+                escaped.push(c);
+            }
+        }
+        escaped
+    }
+}
+
+impl KeyCodec for EscapingCodec {
+    fn join(&self, base: &str, segment: Segment<'_>) -> String {
+        match segment {
+            Segment::Field(name) => self
+                .separators
+                .join(base, Segment::Field(&self.escape(name))),
+            Segment::MapKey(name) => self
+                .separators
+                .join(base, Segment::MapKey(&self.escape(name))),
+            Segment::Index(index) => self.separators.join(base, Segment::Index(index)),
+        }
+    }
+
+    fn len_key(&self, base: &str) -> String {
+        self.separators.len_key(base)
+    }
+
+    fn split_member<'k>(
+        &self,
+        base: &str,
+        key: &'k str,
+        exists: &dyn Fn(&str) -> bool,
+    ) -> Option<Cow<'k, str>> {
+        match self.separators.split_member(base, key, exists)? {
+            Cow::Borrowed(name) if name.contains('%') => Some(Cow::Owned(unescape(name))),
+            name => Some(name),
+        }
+    }
+}
+
+/// Decodes `%XX` sequences in either case, keeping malformed ones as they are
+fn unescape(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let byte = bytes
+            .get(pos + 1..pos + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match byte {
+            Some(byte) if bytes[pos] == b'%' => {
+                decoded.push(byte);
+                pos += 3;
+            }
+            _ => {
+                decoded.push(bytes[pos]);
+                pos += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| name.to_string())
+}
+
+/// Returns the separators of a configuration as a codec of their own
+fn separators(config: &TxtRecordConfig) -> TxtRecordConfig {
+    TxtRecordConfig {
+        key_codec: None,
+        key_dictionary: None,
+        ..config.clone()
+    }
+}
@@ -1,3 +1,11 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::codec::KeyCodec;
+use crate::KeyDictionary;
+
 /// Configuration for TXT record serialization
 #[derive(Debug, Clone)]
 pub struct TxtRecordConfig {
@@ -5,10 +13,254 @@ pub struct TxtRecordConfig {
     pub array_separator: String,
     /// Separator for object fields (default: ".")
     pub object_separator: String,
-    /// Maximum length for each record in format "key=value" (default: 255)
+    /// Maximum length for each record in format "key=value", measured in
+    /// [`length_unit`](Self::length_unit) (default: 255)
     pub record_len: usize,
+    /// Delimiter between key and value, used by the text format and when measuring
+    /// `record_len` (default: "=")
+    pub assignment_delimiter: String,
     /// Suffix for array length metadata keys (default: "_len")
     pub array_len_suffix: String,
+    /// Infer the type of leaf values in `deserialize_any` (default: false)
+    ///
+    /// When enabled, untyped targets such as `serde_json::Value` receive numbers, booleans
+    /// and `null` instead of strings, and strings that look like one of those are quoted
+    /// by the serializer so that they stay strings.
+    ///
+    /// Fields behind `#[serde(flatten)]` are buffered by serde before their type is known,
+    /// so flattened structs with number or boolean fields need this, while a flattened
+    /// catch-all `HashMap<String, String>` only receives strings without it.
+    pub infer_types: bool,
+    /// Case applied to every record key (default: [`KeyCase::Preserve`])
+    ///
+    /// With any other case, keys are also matched without regard to case when reading.
+    pub key_case: KeyCase,
+    /// Root prefix of every record key (default: none)
+    ///
+    /// The serializer writes values under `prefix.`, so that several values can share one
+    /// record set. The deserializer reads only the records under the prefix and ignores the
+    /// others.
+    pub prefix: Option<String>,
+    /// Reject records that no field reads, and records that give a key more than one shape
+    /// such as `a=1` next to `a.b=2` (default: false)
+    ///
+    /// With a [`prefix`](Self::prefix), only records inside that namespace are checked.
+    pub strict: bool,
+    /// Order of the serialized records (default: [`KeyOrder::Preserve`])
+    pub ordering: KeyOrder,
+    /// Unit in which [`record_len`](Self::record_len) is measured (default:
+    /// [`LengthUnit::Bytes`], as DNS limits each character-string to 255 bytes)
+    pub length_unit: LengthUnit,
+    /// Reject records whose key or value contains a non-ASCII character (default: false)
+    pub ascii_only: bool,
+    /// Aliases that shorten record keys (default: none), see [`KeyDictionary`]
+    pub key_dictionary: Option<KeyDictionary>,
+    /// What to do with records longer than [`record_len`](Self::record_len) (default:
+    /// [`OverflowPolicy::Error`])
+    pub overflow: OverflowPolicy,
+    /// Fail serialization on values that will not deserialize to the same value, such as
+    /// enum variants with data or bytes that are not UTF-8 (default: false)
+    pub reject_lossy: bool,
+    /// Layout of record keys (default: none, which joins keys with the separators above)
+    ///
+    /// See the [`codec`](crate::codec) module. The [`prefix`](Self::prefix) is still joined
+    /// with the `object_separator`. Cannot be combined with a
+    /// [`key_dictionary`](Self::key_dictionary).
+    pub key_codec: Option<Arc<dyn KeyCodec>>,
+}
+
+/// Case transform applied to record keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyCase {
+    /// Keep keys as written by the Rust field and map key names
+    #[default]
+    Preserve,
+    /// Uppercase keys, e.g. `DATABASE__HOST`
+    Upper,
+    /// Lowercase keys, e.g. `database.host`
+    Lower,
+}
+
+impl KeyCase {
+    /// Apply the case transform to a key
+    pub fn apply<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match self {
+            KeyCase::Preserve => Cow::Borrowed(key),
+            KeyCase::Upper => Cow::Owned(key.to_uppercase()),
+            KeyCase::Lower => Cow::Owned(key.to_lowercase()),
+        }
+    }
+}
+
+/// Unit in which record lengths are measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LengthUnit {
+    /// UTF-8 bytes, as counted by DNS
+    #[default]
+    Bytes,
+    /// Unicode scalar values, for targets that count characters
+    Chars,
+}
+
+impl LengthUnit {
+    /// Measure the length of a string in this unit
+    pub fn measure(&self, s: &str) -> usize {
+        match self {
+            LengthUnit::Bytes => s.len(),
+            LengthUnit::Chars => s.chars().count(),
+        }
+    }
+}
+
+impl fmt::Display for LengthUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LengthUnit::Bytes => write!(f, "bytes"),
+            LengthUnit::Chars => write!(f, "characters"),
+        }
+    }
+}
+
+/// What the serializer does with a record that is longer than `record_len`
+///
+/// Anything other than failing is reported as an
+/// [`Adjustment`](crate::ser::Adjustment) by
+/// [`to_txt_records_with_adjustments`](crate::to_txt_records_with_adjustments).
+#[derive(Clone, Default)]
+pub enum OverflowPolicy {
+    /// Fail serialization with [`TxtRecordError::RecordTooLong`](crate::TxtRecordError::RecordTooLong)
+    #[default]
+    Error,
+    /// Cut the value at a character boundary and append `marker`, e.g. `"..."`
+    ///
+    /// Fails like [`Error`](Self::Error) if the key and marker alone are too long.
+    Truncate { marker: String },
+    /// Leave the record out
+    ///
+    /// An array element whose records are all left out is left out of the array, and the
+    /// elements after it move up one index.
+    Skip,
+    /// Decide for each record with a function of its key and value
+    Callback(OverflowCallback),
+}
+
+/// A function of a record's key and value that decides what to do with it
+pub type OverflowCallback = Arc<dyn Fn(&str, &str) -> OverflowAction + Send + Sync>;
+
+impl OverflowPolicy {
+    /// Decide for each record with a function of its key and value
+    pub fn callback<F>(callback: F) -> Self
+    where
+        F: Fn(&str, &str) -> OverflowAction + Send + Sync + 'static,
+    {
+        OverflowPolicy::Callback(Arc::new(callback))
+    }
+
+    /// Returns the action for a record that is too long
+    pub(crate) fn action(&self, key: &str, value: &str) -> OverflowAction {
+        match self {
+            OverflowPolicy::Error => OverflowAction::Error,
+            OverflowPolicy::Truncate { marker } => OverflowAction::Truncate {
+                marker: marker.clone(),
+            },
+            OverflowPolicy::Skip => OverflowAction::Skip,
+            OverflowPolicy::Callback(callback) => callback(key, value),
+        }
+    }
+}
+
+impl fmt::Debug for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverflowPolicy::Error => write!(f, "Error"),
+            OverflowPolicy::Truncate { marker } => {
+                f.debug_struct("Truncate").field("marker", marker).finish()
+            }
+            OverflowPolicy::Skip => write!(f, "Skip"),
+            OverflowPolicy::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+/// What an [`OverflowPolicy::Callback`] does with a record that is too long
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverflowAction {
+    Error,
+    Truncate {
+        marker: String,
+    },
+    Skip,
+    /// Write this value instead, which fails like [`Error`](Self::Error) if it is still too
+    /// long
+    Replace(String),
+}
+
+/// Order of serialized records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyOrder {
+    /// Keep the order in which fields are serialized, which is the iteration order for maps
+    /// such as `HashMap`
+    #[default]
+    Preserve,
+    /// Sort keys byte by byte, e.g. `tags_0`, `tags_10`, `tags_2`
+    Lexicographic,
+    /// Sort keys byte by byte, but runs of digits by their numeric value, e.g. `tags_0`,
+    /// `tags_2`, `tags_10`
+    Natural,
+}
+
+impl KeyOrder {
+    /// Sort records in this order
+    ///
+    /// Sorting is deterministic: records with equal keys are ordered by their value.
+    pub fn sort(&self, records: &mut [(String, String)]) {
+        match self {
+            KeyOrder::Preserve => {}
+            KeyOrder::Lexicographic => records.sort(),
+            KeyOrder::Natural => records.sort_by(|(a, a_value), (b, b_value)| {
+                natural_cmp(a, b)
+                    .then_with(|| a.cmp(b))
+                    .then_with(|| a_value.cmp(b_value))
+            }),
+        }
+    }
+}
+
+/// Compares runs of ASCII digits by their numeric value and everything else byte by byte.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_len = a.iter().take_while(|c| c.is_ascii_digit()).count();
+                let b_len = b.iter().take_while(|c| c.is_ascii_digit()).count();
+                // numbers of any length compare without overflowing once leading zeros are gone
+                let a_num = trim_zeros(&a[..a_len]);
+                let b_num = trim_zeros(&b[..b_len]);
+                let ordering = a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = &a[a_len..];
+                b = &b[b_len..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|&&c| c == b'0').count();
+    &digits[zeros..]
 }
 
 impl Default for TxtRecordConfig {
@@ -17,7 +269,373 @@ impl Default for TxtRecordConfig {
             array_separator: "_".to_string(),
             object_separator: ".".to_string(),
             record_len: 255,
+            assignment_delimiter: "=".to_string(),
             array_len_suffix: "_len".to_string(),
+            infer_types: false,
+            key_case: KeyCase::Preserve,
+            prefix: None,
+            strict: false,
+            ordering: KeyOrder::Preserve,
+            length_unit: LengthUnit::Bytes,
+            ascii_only: false,
+            key_dictionary: None,
+            overflow: OverflowPolicy::Error,
+            reject_lossy: false,
+            key_codec: None,
+        }
+    }
+}
+
+impl TxtRecordConfig {
+    /// Start building a configuration from the defaults
+    ///
+    /// ```rust
+    /// use serde_txtrecord::TxtRecordConfig;
+    ///
+    /// let config = TxtRecordConfig::builder()
+    ///     .object_separator("/")
+    ///     .array_len_suffix(".count")
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(config.object_separator, "/");
+    ///
+    /// // "_1" would be read back as an array element
+    /// assert!(TxtRecordConfig::builder().array_len_suffix("_1").build().is_err());
+    /// ```
+    pub fn builder() -> TxtRecordConfigBuilder {
+        TxtRecordConfigBuilder::default()
+    }
+
+    /// Preset for DNS-SD TXT records (RFC 6763)
+    ///
+    /// Records are limited to 255 bytes and keys are kept short: `.` nests objects, `_N`
+    /// indexes arrays and `#` marks array lengths. Keys are lowercased, as DNS-SD compares
+    /// them without regard to case, and records must be ASCII, as RFC 6763 requires of keys.
+    /// Disable [`ascii_only`](Self::ascii_only) to allow UTF-8 values, which the RFC permits.
+    ///
+    /// RFC 6763 recommends keys of at most nine characters, so that a whole record set fits
+    /// in a single packet. The preset does not shorten field names; rename long fields with
+    /// `#[serde(rename)]` or alias them with a [`key_dictionary`](Self::key_dictionary), and
+    /// check the result with [`size_report`](crate::size_report).
+    ///
+    /// ```text
+    /// publisher.name=No Starch Press
+    /// genres_0=Programming
+    /// genres#=1
+    /// ```
+    pub fn dns_sd() -> Self {
+        Self {
+            array_len_suffix: "#".to_string(),
+            key_case: KeyCase::Lower,
+            ascii_only: true,
+            ..Self::default()
+        }
+    }
+
+    /// Preset for generic DNS TXT records, limited to 255 bytes per character-string
+    ///
+    /// ```text
+    /// publisher.name=No Starch Press
+    /// genres_0=Programming
+    /// genres_len=1
+    /// ```
+    pub fn dns() -> Self {
+        Self::default()
+    }
+
+    /// Preset for environment variables
+    ///
+    /// Keys are uppercased and matched without regard to case when reading, `__` nests
+    /// objects, `_N` indexes arrays and `_LEN` marks array lengths. Records are not limited
+    /// in length.
+    ///
+    /// ```text
+    /// PUBLISHER__NAME=No Starch Press
+    /// GENRES_0=Programming
+    /// GENRES_LEN=1
+    /// ```
+    pub fn env() -> Self {
+        Self {
+            object_separator: "__".to_string(),
+            array_len_suffix: "_LEN".to_string(),
+            record_len: usize::MAX,
+            key_case: KeyCase::Upper,
+            ..Self::default()
+        }
+    }
+
+    /// Preset for slash separated key-value stores such as Consul or etcd
+    ///
+    /// `/` nests objects, `_N` indexes arrays and `_len` marks array lengths. Records are not
+    /// limited in length.
+    ///
+    /// ```text
+    /// publisher/name=No Starch Press
+    /// genres_0=Programming
+    /// genres_len=1
+    /// ```
+    pub fn kv_path() -> Self {
+        Self {
+            object_separator: "/".to_string(),
+            record_len: usize::MAX,
+            ..Self::default()
+        }
+    }
+
+    /// Preset for URL query strings and form bodies
+    ///
+    /// `.` nests objects, `_N` indexes arrays and `_len` marks array lengths. Records are not
+    /// limited in length, as the limit applies to the whole URL instead.
+    ///
+    /// ```text
+    /// publisher.name=No Starch Press&genres_0=Programming&genres_len=1
+    /// ```
+    pub fn query_string() -> Self {
+        Self {
+            record_len: usize::MAX,
+            ..Self::default()
         }
     }
+
+    /// Check that records written with this configuration can be read back
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.array_separator.is_empty() {
+            return Err(ConfigError::EmptyArraySeparator);
+        }
+        if self.object_separator.is_empty() {
+            return Err(ConfigError::EmptyObjectSeparator);
+        }
+        if self.array_len_suffix.is_empty() {
+            return Err(ConfigError::EmptyArrayLenSuffix);
+        }
+        if self.assignment_delimiter.is_empty() {
+            return Err(ConfigError::EmptyAssignmentDelimiter);
+        }
+        if self.prefix.as_deref() == Some("") {
+            return Err(ConfigError::EmptyPrefix);
+        }
+        if self.array_separator == self.object_separator {
+            return Err(ConfigError::SameSeparators(self.array_separator.clone()));
+        }
+        if let Some(rest) = self.array_len_suffix.strip_prefix(&self.array_separator) {
+            if rest.starts_with(|c: char| c.is_ascii_digit()) {
+                return Err(ConfigError::AmbiguousArrayLenSuffix {
+                    array_len_suffix: self.array_len_suffix.clone(),
+                    array_separator: self.array_separator.clone(),
+                });
+            }
+        }
+
+        // the shortest record has a single character key and an empty value
+        let min_len = 1 + self.length_unit.measure(&self.assignment_delimiter);
+        if self.record_len < min_len {
+            return Err(ConfigError::RecordLenTooSmall {
+                record_len: self.record_len,
+                min_len,
+            });
+        }
+        if let Some(dictionary) = &self.key_dictionary {
+            if self.key_codec.is_some() {
+                return Err(ConfigError::ConflictingKeyCodec(
+                    "key dictionaries".to_string(),
+                ));
+            }
+            dictionary.validate(self)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builder for [`TxtRecordConfig`] that validates the configuration on [`build`](Self::build)
+#[derive(Debug, Clone, Default)]
+pub struct TxtRecordConfigBuilder {
+    config: TxtRecordConfig,
 }
+
+impl TxtRecordConfigBuilder {
+    pub fn array_separator(mut self, separator: impl Into<String>) -> Self {
+        self.config.array_separator = separator.into();
+        self
+    }
+
+    pub fn object_separator(mut self, separator: impl Into<String>) -> Self {
+        self.config.object_separator = separator.into();
+        self
+    }
+
+    pub fn record_len(mut self, record_len: usize) -> Self {
+        self.config.record_len = record_len;
+        self
+    }
+
+    pub fn assignment_delimiter(mut self, delimiter: impl Into<String>) -> Self {
+        self.config.assignment_delimiter = delimiter.into();
+        self
+    }
+
+    pub fn array_len_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.config.array_len_suffix = suffix.into();
+        self
+    }
+
+    pub fn infer_types(mut self, infer_types: bool) -> Self {
+        self.config.infer_types = infer_types;
+        self
+    }
+
+    pub fn key_case(mut self, key_case: KeyCase) -> Self {
+        self.config.key_case = key_case;
+        self
+    }
+
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.config.prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.strict = strict;
+        self
+    }
+
+    pub fn ordering(mut self, ordering: KeyOrder) -> Self {
+        self.config.ordering = ordering;
+        self
+    }
+
+    pub fn length_unit(mut self, length_unit: LengthUnit) -> Self {
+        self.config.length_unit = length_unit;
+        self
+    }
+
+    pub fn ascii_only(mut self, ascii_only: bool) -> Self {
+        self.config.ascii_only = ascii_only;
+        self
+    }
+
+    pub fn key_dictionary(mut self, key_dictionary: KeyDictionary) -> Self {
+        self.config.key_dictionary = Some(key_dictionary);
+        self
+    }
+
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.config.overflow = overflow;
+        self
+    }
+
+    pub fn reject_lossy(mut self, reject_lossy: bool) -> Self {
+        self.config.reject_lossy = reject_lossy;
+        self
+    }
+
+    pub fn key_codec(mut self, key_codec: impl KeyCodec + 'static) -> Self {
+        self.config.key_codec = Some(Arc::new(key_codec));
+        self
+    }
+
+    /// Validate and return the configuration
+    pub fn build(self) -> Result<TxtRecordConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// A configuration that produces records which cannot be decoded again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    EmptyArraySeparator,
+    EmptyObjectSeparator,
+    EmptyArrayLenSuffix,
+    EmptyAssignmentDelimiter,
+    EmptyPrefix,
+    SameSeparators(String),
+    AmbiguousArrayLenSuffix {
+        array_len_suffix: String,
+        array_separator: String,
+    },
+    RecordLenTooSmall {
+        record_len: usize,
+        min_len: usize,
+    },
+    InvalidAlias {
+        path: String,
+        alias: String,
+    },
+    DuplicateAlias(String),
+    UnpublishedKeyDictionary,
+    EmptyKeyDictionaryRecord,
+    ConflictingKeyCodec(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::EmptyArraySeparator => write!(
+                f,
+                "array_separator is empty, so array elements cannot be told apart from their base key"
+            ),
+            ConfigError::EmptyObjectSeparator => write!(
+                f,
+                "object_separator is empty, so object fields cannot be told apart from their parent key"
+            ),
+            ConfigError::EmptyArrayLenSuffix => write!(
+                f,
+                "array_len_suffix is empty, so array lengths would overwrite the array key"
+            ),
+            ConfigError::EmptyAssignmentDelimiter => write!(
+                f,
+                "assignment_delimiter is empty, so keys cannot be split from values"
+            ),
+            ConfigError::EmptyPrefix => write!(
+                f,
+                "prefix is empty, use None to write records at the root"
+            ),
+            ConfigError::SameSeparators(separator) => write!(
+                f,
+                "array_separator and object_separator are both '{}', so array elements and object fields are indistinguishable",
+                separator
+            ),
+            ConfigError::AmbiguousArrayLenSuffix {
+                array_len_suffix,
+                array_separator,
+            } => write!(
+                f,
+                "array_len_suffix '{}' starts with array_separator '{}' followed by a digit, so length records are read back as array elements",
+                array_len_suffix, array_separator
+            ),
+            ConfigError::RecordLenTooSmall {
+                record_len,
+                min_len,
+            } => write!(
+                f,
+                "record_len {} is smaller than the shortest possible record of {}",
+                record_len, min_len
+            ),
+            ConfigError::InvalidAlias { path, alias } => write!(
+                f,
+                "alias '{}' for '{}' is empty, contains ',', '=' or the object_separator, or ends like an array index, so it cannot be expanded again",
+                alias, path
+            ),
+            ConfigError::DuplicateAlias(alias) => write!(
+                f,
+                "alias '{}' is used for more than one path",
+                alias
+            ),
+            ConfigError::UnpublishedKeyDictionary => write!(
+                f,
+                "key dictionary is derived but has no record, so readers cannot know the aliases"
+            ),
+            ConfigError::EmptyKeyDictionaryRecord => write!(
+                f,
+                "key dictionary record is empty, use None to not publish the dictionary"
+            ),
+            ConfigError::ConflictingKeyCodec(feature) => write!(
+                f,
+                "{} cannot be combined with a key_codec",
+                feature
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
@@ -1,9 +1,13 @@
-use serde::de;
+use serde::de::{self, IntoDeserializer};
 use serde::{Deserialize, Deserializer};
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
-use crate::TxtRecordConfig;
+use crate::codec::Segment;
+use crate::config::ConfigError;
+use crate::infer::{self, Inferred};
+use crate::{alias, TxtPath, TxtRecordConfig, TxtRecords};
 
 #[derive(Debug)]
 pub enum DeserializeError {
@@ -11,6 +15,13 @@ pub enum DeserializeError {
     InvalidFormat(String),
     MissingField(String),
     InvalidValue(String),
+    InvalidMapKey(String),
+    UnknownKeys(Vec<String>),
+    ShapeConflict(ShapeConflict),
+    MissingKeyDictionary(String),
+    InvalidSelector(String),
+    Io(std::io::Error),
+    Config(ConfigError),
 }
 
 impl fmt::Display for DeserializeError {
@@ -20,11 +31,62 @@ impl fmt::Display for DeserializeError {
             DeserializeError::InvalidFormat(msg) => write!(f, "Invalid format: {}", msg),
             DeserializeError::MissingField(field) => write!(f, "Missing field: {}", field),
             DeserializeError::InvalidValue(msg) => write!(f, "Invalid value: {}", msg),
+            DeserializeError::InvalidMapKey(msg) => write!(f, "Invalid map key: {}", msg),
+            DeserializeError::UnknownKeys(keys) => write!(f, "Unknown keys: {}", keys.join(", ")),
+            DeserializeError::ShapeConflict(conflict) => write!(f, "{}", conflict),
+            DeserializeError::MissingKeyDictionary(record) => {
+                write!(f, "Missing key dictionary record: {}", record)
+            }
+            DeserializeError::InvalidSelector(msg) => write!(f, "Invalid selector: {}", msg),
+            DeserializeError::Io(err) => write!(f, "I/O error: {}", err),
+            DeserializeError::Config(err) => write!(f, "Invalid configuration: {}", err),
         }
     }
 }
 
-impl std::error::Error for DeserializeError {}
+impl DeserializeError {
+    /// Returns the paths of the records an error is about, such as the unknown keys of
+    /// strict mode or the records of a shape conflict
+    ///
+    /// Keys that are not values in the layout of `config`, such as length records, are left
+    /// out.
+    pub fn paths(&self, config: &TxtRecordConfig) -> Vec<TxtPath> {
+        match self {
+            DeserializeError::UnknownKeys(keys) => keys
+                .iter()
+                .filter_map(|key| config.parse_path(key))
+                .collect(),
+            DeserializeError::ShapeConflict(conflict) => conflict
+                .records
+                .iter()
+                .filter_map(|(key, _)| config.parse_path(key))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeserializeError::Io(err) => Some(err),
+            DeserializeError::Config(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for DeserializeError {
+    fn from(err: ConfigError) -> Self {
+        DeserializeError::Config(err)
+    }
+}
+
+impl From<std::io::Error> for DeserializeError {
+    fn from(err: std::io::Error) -> Self {
+        DeserializeError::Io(err)
+    }
+}
 
 impl de::Error for DeserializeError {
     fn custom<T: fmt::Display>(msg: T) -> Self {
@@ -32,71 +94,295 @@ impl de::Error for DeserializeError {
     }
 }
 
+/// Records that give one key more than one shape, e.g. `a=1` next to `a.b=2`
+///
+/// The deserializer reads a value first, then an array, then an object, and ignores the
+/// records of the other shapes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShapeConflict {
+    pub key: String,
+    /// The conflicting records, with the value, the array length and the object fields in
+    /// that order
+    pub records: Vec<(String, String)>,
+}
+
+impl fmt::Display for ShapeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Conflicting records at '{}': ", self.key)?;
+        for (i, (key, value)) in self.records.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
 /// A deserializer that converts TXT record format back to Rust data structures
 pub struct TxtRecordDeserializer {
     config: TxtRecordConfig,
     records: HashMap<String, String>,
     current_key: String,
+    // key of the value being deserialized, when only a subtree of the records is read
+    root: String,
+    // keys that were read, to find unknown keys in strict mode
+    consumed: RefCell<HashSet<String>>,
+    // conflicts by key, found once when the deserializer is created and removed when read
+    conflicts: RefCell<HashMap<String, ShapeConflict>>,
+    warnings: RefCell<Vec<ShapeConflict>>,
+    // the current key is an array element, which is null when it has no records
+    element: bool,
 }
 
 impl TxtRecordDeserializer {
-    pub fn new(records: Vec<(String, String)>) -> Self {
+    pub fn new(records: impl Into<TxtRecords>) -> Self {
         Self::with_config(records, TxtRecordConfig::default())
     }
 
-    pub fn with_config(records: Vec<(String, String)>, config: TxtRecordConfig) -> Self {
-        let records_map = records.into_iter().collect();
-        Self {
+    /// Create a deserializer with custom configuration
+    ///
+    /// Keys are expanded with the aliases of the [`key_dictionary`](TxtRecordConfig::key_dictionary)
+    /// as configured, while [`from_txt_records_with_config`] reads the aliases from the published
+    /// dictionary record.
+    pub fn with_config(records: impl Into<TxtRecords>, config: TxtRecordConfig) -> Self {
+        let prefix = config
+            .prefix
+            .as_deref()
+            .map(|prefix| config.key_case.apply(prefix).into_owned());
+        let namespace = prefix.as_ref().map(|prefix| {
+            format!(
+                "{}{}",
+                prefix,
+                config.key_case.apply(&config.object_separator)
+            )
+        });
+
+        let dictionary_key = config
+            .key_dictionary
+            .as_ref()
+            .and_then(|dictionary| dictionary.record_key(&config));
+        let expander = config
+            .key_dictionary
+            .as_ref()
+            .map(|dictionary| alias::KeyExpander::new(dictionary, &config));
+
+        let records_map = records
+            .into()
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let key = config.key_case.apply(&key).into_owned();
+                if dictionary_key.as_ref() == Some(&key) {
+                    return None;
+                }
+                match (&prefix, &namespace) {
+                    (Some(prefix), _) if key == *prefix => Some((String::new(), value)),
+                    (Some(_), Some(namespace)) => key
+                        .strip_prefix(namespace.as_str())
+                        .map(|key| (key.to_string(), value)),
+                    _ => Some((key, value)),
+                }
+            })
+            .map(|(key, value)| match &expander {
+                Some(expander) => (expander.expand(&key), value),
+                None => (key, value),
+            })
+            .collect();
+        let mut deserializer = Self {
             config,
             records: records_map,
             current_key: String::new(),
-        }
+            root: String::new(),
+            consumed: RefCell::new(HashSet::new()),
+            conflicts: RefCell::new(HashMap::new()),
+            warnings: RefCell::new(Vec::new()),
+            element: false,
+        };
+        deserializer.conflicts = RefCell::new(deserializer.find_conflicts());
+        deserializer
     }
 
-    fn get_value(&self, key: &str) -> Option<&String> {
-        self.records.get(key)
+    /// Returns the shape conflicts found so far, which are errors if the configuration is
+    /// strict
+    pub fn warnings(&self) -> Vec<ShapeConflict> {
+        self.warnings.borrow().clone()
     }
 
-    fn get_array_length(&self, base_key: &str) -> Option<usize> {
-        let len_key = format!("{}{}", base_key, self.config.array_len_suffix);
-        self.get_value(&len_key).and_then(|s| s.parse().ok())
+    /// Check that every record was read, if the configuration is strict
+    ///
+    /// Records outside the [`prefix`](TxtRecordConfig::prefix) were never part of the input
+    /// and are not reported.
+    pub fn end(&self) -> Result<(), DeserializeError> {
+        if !self.config.strict {
+            return Ok(());
+        }
+
+        let consumed = self.consumed.borrow();
+        let mut unknown: Vec<String> = self
+            .records
+            .keys()
+            .filter(|key| !consumed.contains(*key) && self.in_subtree(key))
+            .map(|key| self.full_key(key))
+            .collect();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        unknown.sort();
+        Err(DeserializeError::UnknownKeys(unknown))
     }
 
-    fn get_object_keys(&self, base_key: &str) -> Vec<String> {
-        let prefix = if base_key.is_empty() {
-            String::new()
-        } else {
-            format!("{}{}", base_key, self.config.object_separator)
+    /// Returns true if a record is part of the value being deserialized, which is every
+    /// record unless [`from_txt_records_at`] reads a subtree
+    fn in_subtree(&self, key: &str) -> bool {
+        if self.root.is_empty() {
+            return true;
+        }
+        let case = self.config.key_case;
+        let codec = self.config.codec();
+        let root = case.apply(&self.root);
+        let exists = |key: &str| self.records.contains_key(key);
+        key == root
+            || key == case.apply(&codec.len_key(&self.root))
+            || self.config.split_index(&root, key).is_some()
+            || codec.split_member(&root, key, &exists).is_some()
+    }
+
+    /// Returns a key as it appears in the input, with the prefix
+    fn full_key(&self, key: &str) -> String {
+        let key = match &self.config.prefix {
+            Some(prefix) if key.is_empty() => prefix.clone(),
+            Some(prefix) => format!("{}{}{}", prefix, self.config.object_separator, key),
+            None => key.to_string(),
         };
+        self.config.key_case.apply(&key).into_owned()
+    }
 
-        let mut keys = std::collections::HashSet::new();
+    /// Returns the keys that are more than one of a value, an array and an object
+    ///
+    /// Every record key is split into the objects and arrays above it once, so that reading
+    /// a value only looks its key up.
+    fn find_conflicts(&self) -> HashMap<String, ShapeConflict> {
+        let case = self.config.key_case;
+        let codec = self.config.codec();
+        let exists = |key: &str| self.records.contains_key(key);
+
+        // the records below each object, by the key of the object
+        let mut fields: HashMap<String, Vec<&String>> = HashMap::new();
         for record_key in self.records.keys() {
-            if base_key.is_empty() {
-                // for root level, any key that doesn't contain separators is a direct key
-                if !record_key.contains(&self.config.object_separator)
-                    && !record_key.contains(&self.config.array_separator)
-                    && !record_key.ends_with(&self.config.array_len_suffix)
-                {
-                    keys.insert(record_key.clone());
-                } else if let Some(dot_pos) = record_key.find(&self.config.object_separator) {
-                    // or the first part of a nested key
-                    keys.insert(record_key[..dot_pos].to_string());
-                } else if let Some(array_pos) = record_key.find(&self.config.array_separator) {
-                    // or the base name of an array
-                    let base_name = &record_key[..array_pos];
-                    if !base_name.is_empty() {
-                        keys.insert(base_name.to_string());
+            let mut base = String::new();
+            while base != *record_key {
+                let next = if let Some((_, next)) = self.config.split_index(&base, record_key) {
+                    next
+                } else if let Some(name) = codec.split_member(&base, record_key, &exists) {
+                    let next = case
+                        .apply(&codec.join(&base, Segment::MapKey(&name)))
+                        .into_owned();
+                    if !base.is_empty() {
+                        fields.entry(base.clone()).or_default().push(record_key);
                     }
+                    next
+                } else {
+                    break;
+                };
+                if next.len() <= base.len() || !record_key.starts_with(next.as_str()) {
+                    break;
                 }
-            } else if record_key.starts_with(&prefix) {
-                let suffix = &record_key[prefix.len()..];
-                if let Some(dot_pos) = suffix.find(&self.config.object_separator) {
-                    keys.insert(suffix[..dot_pos].to_string());
-                } else if !suffix.contains(&self.config.array_separator)
-                    && !suffix.ends_with(&self.config.array_len_suffix)
-                {
-                    keys.insert(suffix.to_string());
-                }
+                base = next;
+            }
+        }
+
+        let mut conflicts = HashMap::new();
+        for key in self.records.keys().chain(fields.keys()) {
+            let key = key.as_str();
+            if key.is_empty() || conflicts.contains_key(key) {
+                continue;
+            }
+            let len_key = case.apply(&codec.len_key(key)).into_owned();
+            let mut members = fields.get(key).cloned().unwrap_or_default();
+            let is_value = self.records.contains_key(key);
+            // a codec may read the length record as a field, e.g. `range[len]`
+            let is_array = self.records.contains_key(&len_key)
+                && !members.iter().any(|member| **member == len_key);
+            if [is_value, is_array, !members.is_empty()]
+                .iter()
+                .filter(|&&shape| shape)
+                .count()
+                < 2
+            {
+                continue;
+            }
+
+            members.sort();
+            let records = [key, len_key.as_str()]
+                .into_iter()
+                .chain(members.into_iter().map(String::as_str))
+                .filter_map(|record_key| {
+                    let value = self.records.get(record_key)?;
+                    Some((self.full_key(record_key), value.clone()))
+                })
+                .collect();
+            let conflict = ShapeConflict {
+                key: self.full_key(key),
+                records,
+            };
+            conflicts.insert(key.to_string(), conflict);
+        }
+        conflicts
+    }
+
+    /// Check that a key is only a value, an array or an object
+    ///
+    /// A conflict fails in strict mode, and is kept as a warning the first time it is read
+    /// otherwise.
+    fn check_shape(&self, key: &str) -> Result<(), DeserializeError> {
+        let key = self.config.key_case.apply(key);
+        let Some(conflict) = self.conflicts.borrow_mut().remove(key.as_ref()) else {
+            return Ok(());
+        };
+        if self.config.strict {
+            return Err(DeserializeError::ShapeConflict(conflict));
+        }
+        self.warnings.borrow_mut().push(conflict);
+        Ok(())
+    }
+
+    fn get_value(&self, key: &str) -> Result<Option<&String>, DeserializeError> {
+        self.check_shape(key)?;
+        let key = self.config.key_case.apply(key);
+        let Some((key, value)) = self.records.get_key_value(key.as_ref()) else {
+            return Ok(None);
+        };
+        if self.config.strict {
+            self.consumed.borrow_mut().insert(key.clone());
+        }
+        Ok(Some(value))
+    }
+
+    /// Returns a string value, without the quotes added for inferred types.
+    fn get_str(&self, key: &str) -> Result<Option<&str>, DeserializeError> {
+        let value = self.get_value(key)?;
+        if self.config.infer_types {
+            Ok(value.map(|value| infer::unquote(value)))
+        } else {
+            Ok(value.map(String::as_str))
+        }
+    }
+
+    fn get_array_length(&self, base_key: &str) -> Result<Option<usize>, DeserializeError> {
+        self.check_shape(base_key)?;
+        let len_key = self.config.codec().len_key(base_key);
+        Ok(self.get_value(&len_key)?.and_then(|s| s.parse().ok()))
+    }
+
+    fn get_object_keys(&self, base_key: &str) -> Vec<String> {
+        let base_key = self.config.key_case.apply(base_key);
+        let exists = |key: &str| self.records.contains_key(key);
+        let codec = self.config.codec();
+
+        let mut keys = HashSet::new();
+        for record_key in self.records.keys() {
+            if let Some(member) = codec.split_member(&base_key, record_key, &exists) {
+                keys.insert(member.into_owned());
             }
         }
         keys.into_iter().collect()
@@ -111,15 +397,28 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
         V: de::Visitor<'de>,
     {
         // try to determine the type based on the current key
-        if let Some(value) = self.get_value(&self.current_key) {
+        if let Some(value) = self.get_value(&self.current_key)? {
             // it's a simple value
-            visitor.visit_str(value)
-        } else if self.get_array_length(&self.current_key).is_some() {
+            if !self.config.infer_types {
+                return visitor.visit_str(value);
+            }
+            match infer::infer(value) {
+                Inferred::Null => visitor.visit_unit(),
+                Inferred::Bool(b) => visitor.visit_bool(b),
+                Inferred::Unsigned(n) => visitor.visit_u64(n),
+                Inferred::Signed(n) => visitor.visit_i64(n),
+                Inferred::Float(n) => visitor.visit_f64(n),
+                Inferred::Str(s) => visitor.visit_str(s),
+            }
+        } else if self.get_array_length(&self.current_key)?.is_some() {
             // it's an array
             self.deserialize_seq(visitor)
         } else if !self.get_object_keys(&self.current_key).is_empty() {
             // it's an object
             self.deserialize_map(visitor)
+        } else if self.element {
+            // null elements write no records, but keep their place below the length
+            visitor.visit_unit()
         } else {
             Err(DeserializeError::MissingField(self.current_key.clone()))
         }
@@ -129,7 +428,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<bool>() {
                 Ok(b) => visitor.visit_bool(b),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -145,7 +444,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<i8>() {
                 Ok(n) => visitor.visit_i8(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -161,7 +460,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<i16>() {
                 Ok(n) => visitor.visit_i16(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -177,7 +476,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<i32>() {
                 Ok(n) => visitor.visit_i32(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -193,7 +492,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<i64>() {
                 Ok(n) => visitor.visit_i64(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -205,11 +504,27 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
         }
     }
 
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.get_value(&self.current_key)? {
+            Some(value) => match value.parse::<i128>() {
+                Ok(n) => visitor.visit_i128(n),
+                Err(_) => Err(DeserializeError::InvalidValue(format!(
+                    "Cannot parse '{}' as i128",
+                    value
+                ))),
+            },
+            None => Err(DeserializeError::MissingField(self.current_key.clone())),
+        }
+    }
+
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<u8>() {
                 Ok(n) => visitor.visit_u8(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -225,7 +540,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<u16>() {
                 Ok(n) => visitor.visit_u16(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -241,7 +556,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<u32>() {
                 Ok(n) => visitor.visit_u32(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -257,7 +572,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<u64>() {
                 Ok(n) => visitor.visit_u64(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -269,11 +584,27 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
         }
     }
 
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.get_value(&self.current_key)? {
+            Some(value) => match value.parse::<u128>() {
+                Ok(n) => visitor.visit_u128(n),
+                Err(_) => Err(DeserializeError::InvalidValue(format!(
+                    "Cannot parse '{}' as u128",
+                    value
+                ))),
+            },
+            None => Err(DeserializeError::MissingField(self.current_key.clone())),
+        }
+    }
+
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<f32>() {
                 Ok(n) => visitor.visit_f32(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -289,7 +620,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_value(&self.current_key)? {
             Some(value) => match value.parse::<f64>() {
                 Ok(n) => visitor.visit_f64(n),
                 Err(_) => Err(DeserializeError::InvalidValue(format!(
@@ -305,7 +636,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_str(&self.current_key)? {
             Some(value) => {
                 let mut chars = value.chars();
                 match (chars.next(), chars.next()) {
@@ -324,7 +655,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_str(&self.current_key)? {
             Some(value) => visitor.visit_str(value),
             None => Err(DeserializeError::MissingField(self.current_key.clone())),
         }
@@ -341,7 +672,7 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        match self.get_value(&self.current_key) {
+        match self.get_str(&self.current_key)? {
             Some(value) => visitor.visit_bytes(value.as_bytes()),
             None => Err(DeserializeError::MissingField(self.current_key.clone())),
         }
@@ -359,8 +690,8 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
         V: de::Visitor<'de>,
     {
         // For options, check if we have either a direct value, an array, or an object
-        if self.get_value(&self.current_key).is_some()
-            || self.get_array_length(&self.current_key).is_some()
+        if self.get_value(&self.current_key)?.is_some()
+            || self.get_array_length(&self.current_key)?.is_some()
             || !self.get_object_keys(&self.current_key).is_empty()
         {
             visitor.visit_some(self)
@@ -402,11 +733,8 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
-        let len = self.get_array_length(&self.current_key).ok_or_else(|| {
-            DeserializeError::MissingField(format!(
-                "{}{}",
-                self.current_key, self.config.array_len_suffix
-            ))
+        let len = self.get_array_length(&self.current_key)?.ok_or_else(|| {
+            DeserializeError::MissingField(self.config.codec().len_key(&self.current_key))
         })?;
 
         visitor.visit_seq(SeqAccess::new(self, len))
@@ -435,8 +763,9 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
+        self.check_shape(&self.current_key)?;
         let keys = self.get_object_keys(&self.current_key);
-        visitor.visit_map(MapAccess::new(self, keys))
+        visitor.visit_map(MapAccess::new(self, keys, false))
     }
 
     fn deserialize_struct<V>(
@@ -448,8 +777,9 @@ impl<'de> Deserializer<'de> for &mut TxtRecordDeserializer {
     where
         V: de::Visitor<'de>,
     {
+        self.check_shape(&self.current_key)?;
         let keys = fields.iter().map(|s| s.to_string()).collect();
-        visitor.visit_map(MapAccess::new(self, keys))
+        visitor.visit_map(MapAccess::new(self, keys, true))
     }
 
     fn deserialize_enum<V>(
@@ -509,14 +839,17 @@ impl<'de, 'a> de::SeqAccess<'de> for SeqAccess<'a> {
             return Ok(None);
         }
 
-        let key = format!(
-            "{}{}{}",
-            self.base_key, self.de.config.array_separator, self.index
-        );
-        self.de.current_key = key;
+        self.de.current_key = self
+            .de
+            .config
+            .codec()
+            .join(&self.base_key, Segment::Index(self.index));
         self.index += 1;
 
-        seed.deserialize(&mut *self.de).map(Some)
+        let element = std::mem::replace(&mut self.de.element, true);
+        let value = seed.deserialize(&mut *self.de).map(Some);
+        self.de.element = element;
+        value
     }
 }
 
@@ -525,16 +858,19 @@ struct MapAccess<'a> {
     base_key: String,
     keys: Vec<String>,
     key_index: usize,
+    // struct fields rather than map keys
+    fields: bool,
 }
 
 impl<'a> MapAccess<'a> {
-    fn new(de: &'a mut TxtRecordDeserializer, keys: Vec<String>) -> Self {
+    fn new(de: &'a mut TxtRecordDeserializer, keys: Vec<String>, fields: bool) -> Self {
         let base_key = de.current_key.clone();
         Self {
             de,
             base_key,
             keys,
             key_index: 0,
+            fields,
         }
     }
 }
@@ -563,23 +899,56 @@ impl<'de, 'a> de::MapAccess<'de> for MapAccess<'a> {
         let key = &self.keys[self.key_index];
         self.key_index += 1;
 
-        if self.base_key.is_empty() {
-            self.de.current_key = key.clone();
+        let segment = if self.fields {
+            Segment::Field(key)
         } else {
-            self.de.current_key = format!(
-                "{}{}{}",
-                self.base_key, self.de.config.object_separator, key
-            );
-        }
+            Segment::MapKey(key)
+        };
+        self.de.current_key = self.de.config.codec().join(&self.base_key, segment);
 
-        seed.deserialize(&mut *self.de)
+        let element = std::mem::replace(&mut self.de.element, false);
+        let value = seed.deserialize(&mut *self.de);
+        self.de.element = element;
+        value
     }
 }
 
+/// Deserializes a map key from the key segment of a record.
+///
+/// Map keys are always stored as strings, so they are parsed into integers, bools,
+/// chars or unit enum variants when the target type asks for them.
 struct KeyDeserializer {
     key: String,
 }
 
+impl KeyDeserializer {
+    fn parse<T: std::str::FromStr>(&self, typ: &str) -> Result<T, DeserializeError> {
+        self.key.parse::<T>().map_err(|_| {
+            DeserializeError::InvalidMapKey(format!("Cannot parse '{}' as {}", self.key, typ))
+        })
+    }
+
+    fn unsupported(&self, kind: &str) -> DeserializeError {
+        DeserializeError::InvalidMapKey(format!(
+            "'{}' cannot be deserialized as a {} map key",
+            self.key, kind
+        ))
+    }
+}
+
+macro_rules! deserialize_parsed_key {
+    ($($method:ident => $visit:ident: $ty:ty),* $(,)?) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: de::Visitor<'de>,
+            {
+                visitor.$visit(self.parse::<$ty>(stringify!($ty))?)
+            }
+        )*
+    };
+}
+
 impl<'de> Deserializer<'de> for &mut KeyDeserializer {
     type Error = DeserializeError;
 
@@ -590,6 +959,23 @@ impl<'de> Deserializer<'de> for &mut KeyDeserializer {
         visitor.visit_str(&self.key)
     }
 
+    deserialize_parsed_key! {
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_i128 => visit_i128: i128,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_u128 => visit_u128: u128,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+        deserialize_char => visit_char: char,
+    }
+
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
@@ -604,6 +990,51 @@ impl<'de> Deserializer<'de> for &mut KeyDeserializer {
         visitor.visit_string(self.key.clone())
     }
 
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_bytes(self.key.as_bytes())
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        // only unit variants can be named by a single key segment
+        visitor.visit_enum(self.key.as_str().into_deserializer())
+    }
+
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
@@ -611,10 +1042,74 @@ impl<'de> Deserializer<'de> for &mut KeyDeserializer {
         self.deserialize_str(visitor)
     }
 
-    serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char bytes
-        byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum ignored_any
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(self.unsupported("unit"))
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        name: &'static str,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(self.unsupported(name))
+    }
+
+    fn deserialize_seq<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(self.unsupported("sequence"))
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(self.unsupported("tuple"))
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(self.unsupported(name))
+    }
+
+    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(self.unsupported("map"))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(self.unsupported(name))
     }
 }
 
@@ -685,7 +1180,7 @@ impl<'de, 'a> de::VariantAccess<'de> for VariantAccess<'a> {
 }
 
 /// Deserialize TXT records back to a Rust data structure
-pub fn from_txt_records<T>(records: Vec<(String, String)>) -> Result<T, DeserializeError>
+pub fn from_txt_records<T>(records: impl Into<TxtRecords>) -> Result<T, DeserializeError>
 where
     T: for<'de> Deserialize<'de>,
 {
@@ -694,12 +1189,89 @@ where
 
 /// Deserialize TXT records back to a Rust data structure with custom configuration
 pub fn from_txt_records_with_config<T>(
-    records: Vec<(String, String)>,
+    records: impl Into<TxtRecords>,
+    config: TxtRecordConfig,
+) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_txt_records_with_warnings(records, config).map(|(value, _)| value)
+}
+
+/// Deserialize TXT records with custom configuration, returning the shape conflicts that
+/// were ignored because the configuration is not strict
+pub fn from_txt_records_with_warnings<T>(
+    records: impl Into<TxtRecords>,
+    config: TxtRecordConfig,
+) -> Result<(T, Vec<ShapeConflict>), DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let mut deserializer = resolved_deserializer(records.into(), config)?;
+    let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok((value, deserializer.warnings.into_inner()))
+}
+
+/// Deserialize the value at a path, such as `services.database`, from TXT records
+pub fn from_txt_records_at<T>(
+    records: impl Into<TxtRecords>,
+    path: &TxtPath,
+) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_txt_records_at_with_config(records, path, TxtRecordConfig::default())
+}
+
+/// Deserialize the value at a path from TXT records with custom configuration
+///
+/// The path is below the [`prefix`](TxtRecordConfig::prefix), and strict mode only reports
+/// the unknown keys below the path.
+pub fn from_txt_records_at_with_config<T>(
+    records: impl Into<TxtRecords>,
+    path: &TxtPath,
     config: TxtRecordConfig,
 ) -> Result<T, DeserializeError>
 where
     T: for<'de> Deserialize<'de>,
 {
-    let mut deserializer = TxtRecordDeserializer::with_config(records, config);
-    T::deserialize(&mut deserializer)
+    resolved_deserializer(records.into(), config)?.deserialize_at(path)
+}
+
+impl TxtRecordDeserializer {
+    /// Deserialize the value at a path, which can be called again for other paths of the
+    /// same records
+    pub(crate) fn deserialize_at<T>(&mut self, path: &TxtPath) -> Result<T, DeserializeError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.root = self.config.path_key(path);
+        self.current_key = self.root.clone();
+        self.element = false;
+        if !self.records.keys().any(|key| self.in_subtree(key)) {
+            return Err(DeserializeError::MissingField(
+                self.config.format_path(path),
+            ));
+        }
+        let value = T::deserialize(&mut *self)?;
+        self.end()?;
+        Ok(value)
+    }
+}
+
+/// Create a deserializer with the aliases of the published key dictionary, if any
+pub(crate) fn resolved_deserializer(
+    records: TxtRecords,
+    config: TxtRecordConfig,
+) -> Result<TxtRecordDeserializer, DeserializeError> {
+    config.validate()?;
+    let config = match alias::resolve(&records, &config)? {
+        Some(dictionary) => TxtRecordConfig {
+            key_dictionary: Some(dictionary),
+            ..config
+        },
+        None => config,
+    };
+    Ok(TxtRecordDeserializer::with_config(records, config))
 }
@@ -0,0 +1,124 @@
+//! Differences between two record sets, for updating only the TXT records that changed.
+//!
+//! Records are compared by key, so shrinking an array removes its trailing elements and
+//! changes its length record, while growing it adds elements.
+//!
+//! ```rust
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! struct Service {
+//!     port: u16,
+//!     tags: Vec<String>,
+//! }
+//!
+//! let old = Service { port: 80, tags: vec!["a".to_string(), "b".to_string()] };
+//! let new = Service { port: 80, tags: vec!["c".to_string()] };
+//!
+//! let diff = serde_txtrecord::diff_values(&old, &new).unwrap();
+//! assert!(diff.added.is_empty());
+//! assert_eq!(diff.removed, vec![("tags_1".to_string(), "b".to_string())]);
+//! assert_eq!(diff.changed.len(), 2); // tags_0 and tags_len
+//!
+//! let mut records = serde_txtrecord::to_txt_records(&old).unwrap();
+//! serde_txtrecord::diff::apply(&mut records, &diff);
+//! assert_eq!(records, serde_txtrecord::to_txt_records(&new).unwrap());
+//! ```
+
+use serde::Serialize;
+
+use crate::{to_txt_records_with_config, TxtRecordConfig, TxtRecordError, TxtRecords};
+
+/// The records that differ between two record sets
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TxtDiff {
+    /// Records only in the new set, in the order of the new set
+    pub added: Vec<(String, String)>,
+    /// Records only in the old set with their old value, in the order of the old set
+    pub removed: Vec<(String, String)>,
+    /// Records in both sets whose value changed, in the order of the new set
+    pub changed: Vec<Change>,
+}
+
+/// A record whose value changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub key: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl TxtDiff {
+    /// Returns true if both record sets were equal
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Returns the number of records that were added, removed or changed
+    pub fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+}
+
+/// Compare two record sets by key
+pub fn diff(old: &TxtRecords, new: &TxtRecords) -> TxtDiff {
+    let mut diff = TxtDiff::default();
+    for (key, value) in new {
+        match old.get(key) {
+            None => diff.added.push((key.clone(), value.clone())),
+            Some(old) if old != value => diff.changed.push(Change {
+                key: key.clone(),
+                old: old.to_string(),
+                new: value.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (key, value) in old {
+        if !new.contains_key(key) {
+            diff.removed.push((key.clone(), value.clone()));
+        }
+    }
+    diff
+}
+
+/// Serialize two values and compare their records
+pub fn diff_values<T>(old: &T, new: &T) -> Result<TxtDiff, TxtRecordError>
+where
+    T: Serialize,
+{
+    diff_values_with_config(old, new, TxtRecordConfig::default())
+}
+
+/// Serialize two values with custom configuration and compare their records
+pub fn diff_values_with_config<T>(
+    old: &T,
+    new: &T,
+    config: TxtRecordConfig,
+) -> Result<TxtDiff, TxtRecordError>
+where
+    T: Serialize,
+{
+    let old = to_txt_records_with_config(old, config.clone())?;
+    let new = to_txt_records_with_config(new, config)?;
+    Ok(diff(&old, &new))
+}
+
+/// Apply a diff to a record set
+///
+/// Removed records are dropped and changed records are updated in place, and added records
+/// are appended. Records are matched by key only, so a diff can be applied to a set that has
+/// changed since it was computed.
+pub fn apply(records: &mut TxtRecords, diff: &TxtDiff) {
+    for (key, _) in &diff.removed {
+        records.remove(key);
+    }
+    for change in &diff.changed {
+        if records.contains_key(&change.key) {
+            records.insert(change.key.as_str(), change.new.as_str());
+        }
+    }
+    for (key, value) in &diff.added {
+        records.insert(key.as_str(), value.as_str());
+    }
+}
@@ -0,0 +1,263 @@
+//! Environment variables and `.env` files.
+//!
+//! Both use the [`TxtRecordConfig::env`] grammar by default: keys are uppercase, `__` nests
+//! objects and arrays are written as `NAME_0`, `NAME_1` and `NAME_LEN`. Keys are matched
+//! without regard to case when reading.
+//!
+//! ```rust
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Database {
+//!     host: String,
+//!     port: u16,
+//! }
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct App {
+//!     name: String,
+//!     database: Database,
+//! }
+//!
+//! let app = App {
+//!     name: "my app".to_string(),
+//!     database: Database { host: "localhost".to_string(), port: 5432 },
+//! };
+//!
+//! let dotenv = serde_txtrecord::to_dotenv(&app).unwrap();
+//! assert_eq!(dotenv, "NAME=\"my app\"\nDATABASE__HOST=localhost\nDATABASE__PORT=5432\n");
+//!
+//! let parsed: App = serde_txtrecord::from_dotenv(&dotenv).unwrap();
+//! assert_eq!(app, parsed);
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+
+use crate::{
+    from_txt_records_with_config, to_txt_records_with_config, DeserializeError, TxtRecordConfig,
+    TxtRecordError, TxtRecords,
+};
+
+/// Deserialize a value from the environment variables that start with `prefix`
+///
+/// The prefix is stripped from each variable name, so with the prefix `APP_` the variable
+/// `APP_DATABASE__HOST` is read as the `database.host` field.
+pub fn from_env<T>(prefix: &str) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_env_with_config(prefix, TxtRecordConfig::env())
+}
+
+/// Deserialize a value from the environment variables that start with `prefix`, with custom
+/// configuration
+pub fn from_env_with_config<T>(prefix: &str, config: TxtRecordConfig) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_vars_with_config(std::env::vars_os(), prefix, config)
+}
+
+/// Deserialize a value from `(name, value)` pairs that start with `prefix`, with custom
+/// configuration
+///
+/// This is what [`from_env_with_config`] does with `std::env::vars_os()`. The prefix is
+/// matched with the same case transform as the keys. Variables that are not valid UTF-8 are
+/// skipped, unless their name starts with the prefix, which is an error.
+pub fn from_vars_with_config<I, K, V, T>(
+    vars: I,
+    prefix: &str,
+    config: TxtRecordConfig,
+) -> Result<T, DeserializeError>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Into<OsString>,
+    V: Into<OsString>,
+    T: for<'de> Deserialize<'de>,
+{
+    let prefix = config.key_case.apply(prefix).into_owned();
+    let mut records = TxtRecords::new();
+    for (name, value) in vars {
+        let (name, value) = (name.into(), value.into());
+        let lossy_name = config.key_case.apply(&name.to_string_lossy()).into_owned();
+        let Some(key) = lossy_name
+            .strip_prefix(&prefix)
+            .filter(|key| !key.is_empty())
+        else {
+            continue;
+        };
+        if name.to_str().is_none() || value.to_str().is_none() {
+            return Err(DeserializeError::InvalidValue(format!(
+                "Environment variable {} is not valid UTF-8",
+                name.to_string_lossy()
+            )));
+        }
+        records.insert(key, value.to_string_lossy());
+    }
+    from_txt_records_with_config(records, config)
+}
+
+/// Serialize a value to the contents of a `.env` file
+pub fn to_dotenv<T>(value: &T) -> Result<String, TxtRecordError>
+where
+    T: Serialize,
+{
+    to_dotenv_with_config(value, TxtRecordConfig::env())
+}
+
+/// Serialize a value to the contents of a `.env` file with custom configuration
+///
+/// Values that are empty or contain whitespace, quotes, `#`, `$`, `` ` ``, `\` or `=` are
+/// written in double quotes, with `\n`, `\r`, `\t`, `\"`, `\$`, `` \` `` and `\\` escapes.
+pub fn to_dotenv_with_config<T>(
+    value: &T,
+    config: TxtRecordConfig,
+) -> Result<String, TxtRecordError>
+where
+    T: Serialize,
+{
+    let records = to_txt_records_with_config(value, config)?;
+
+    let mut output = String::new();
+    for (key, value) in records {
+        output.push_str(&key);
+        output.push('=');
+        output.push_str(&quote(&value));
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// Deserialize a value from the contents of a `.env` file
+pub fn from_dotenv<T>(input: &str) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_dotenv_with_config(input, TxtRecordConfig::env())
+}
+
+/// Deserialize a value from the contents of a `.env` file with custom configuration
+///
+/// Blank lines, `#` comments and a leading `export` are skipped. Values may be unquoted,
+/// single quoted (taken literally) or double quoted (with escapes).
+pub fn from_dotenv_with_config<T>(
+    input: &str,
+    config: TxtRecordConfig,
+) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let records = parse_dotenv(input)?;
+    from_txt_records_with_config(records, config)
+}
+
+/// Parse the `(name, value)` pairs of a `.env` file
+///
+/// A later assignment replaces an earlier assignment of the same variable.
+pub fn parse_dotenv(input: &str) -> Result<TxtRecords, DeserializeError> {
+    let mut records = TxtRecords::new();
+    let mut lines = input.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line.split_once('=').ok_or_else(|| {
+            DeserializeError::InvalidFormat(format!("line {}: missing '='", number + 1))
+        })?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(DeserializeError::InvalidFormat(format!(
+                "line {}: missing variable name",
+                number + 1
+            )));
+        }
+
+        let value = value.trim_start();
+        let value = if let Some(quoted) = value.strip_prefix('"') {
+            // double quoted values may span several lines
+            let mut quoted = quoted.to_string();
+            loop {
+                if let Some(value) = parse_double_quoted(&quoted) {
+                    break value;
+                }
+                let (_, next) = lines.next().ok_or_else(|| {
+                    DeserializeError::InvalidFormat(format!(
+                        "line {}: unterminated double quote",
+                        number + 1
+                    ))
+                })?;
+                quoted.push('\n');
+                quoted.push_str(next);
+            }
+        } else if let Some(quoted) = value.strip_prefix('\'') {
+            let end = quoted.find('\'').ok_or_else(|| {
+                DeserializeError::InvalidFormat(format!(
+                    "line {}: unterminated single quote",
+                    number + 1
+                ))
+            })?;
+            quoted[..end].to_string()
+        } else {
+            // unquoted values end at a comment
+            let value = match value.find(" #") {
+                Some(pos) => &value[..pos],
+                None => value,
+            };
+            value.trim_end().to_string()
+        };
+
+        records.insert(key, value);
+    }
+    Ok(records)
+}
+
+/// Parses the remainder of a double quoted value, or returns `None` if the closing quote is
+/// missing.
+fn parse_double_quoted(quoted: &str) -> Option<String> {
+    let mut value = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+fn quote(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '#' | '$' | '\\' | '=' | '`'));
+    if !needs_quotes {
+        return value.to_string();
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '"' | '$' | '\\' | '`' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,98 @@
+//! Type inference for leaf values of self-describing targets.
+//!
+//! With [`TxtRecordConfig::infer_types`](crate::TxtRecordConfig::infer_types) enabled,
+//! leaf values that look like `null`, booleans, integers or floats are visited with the
+//! matching typed visitor method in `deserialize_any`. Strings that would otherwise be
+//! mistaken for one of these are wrapped in double quotes by the serializer, e.g. the
+//! string `"42"` is written as `key="42"`.
+
+use std::borrow::Cow;
+
+/// The typed interpretation of a leaf value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Inferred<'a> {
+    Null,
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    Str(&'a str),
+}
+
+/// Infers the type of a leaf value, stripping the quotes of quoted strings.
+pub(crate) fn infer(value: &str) -> Inferred<'_> {
+    if is_quoted(value) {
+        return Inferred::Str(&value[1..value.len() - 1]);
+    }
+
+    match value {
+        "null" => return Inferred::Null,
+        "true" => return Inferred::Bool(true),
+        "false" => return Inferred::Bool(false),
+        _ => {}
+    }
+
+    if is_integer(value) {
+        if let Ok(n) = value.parse::<u64>() {
+            return Inferred::Unsigned(n);
+        }
+        if let Ok(n) = value.parse::<i64>() {
+            return Inferred::Signed(n);
+        }
+    }
+
+    if is_float(value) {
+        if let Ok(n) = value.parse::<f64>() {
+            return Inferred::Float(n);
+        }
+    }
+
+    Inferred::Str(value)
+}
+
+/// Quotes a string if it would otherwise be inferred as something else.
+pub(crate) fn quote(value: &str) -> Cow<'_, str> {
+    match infer(value) {
+        Inferred::Str(s) if s.len() == value.len() => Cow::Borrowed(value),
+        _ => Cow::Owned(format!("\"{}\"", value)),
+    }
+}
+
+/// Strips the quotes of a quoted string, leaving other values untouched.
+pub(crate) fn unquote(value: &str) -> &str {
+    if is_quoted(value) {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+fn is_quoted(value: &str) -> bool {
+    value.len() >= 2 && value.starts_with('"') && value.ends_with('"')
+}
+
+fn is_integer(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Matches `-?digits(.digits)?([eE][+-]?digits)?`, so that `inf` or `NaN` stay strings.
+fn is_float(value: &str) -> bool {
+    let rest = value.strip_prefix('-').unwrap_or(value);
+    let (mantissa, exponent) = match rest.find(['e', 'E']) {
+        Some(pos) => (&rest[..pos], Some(&rest[pos + 1..])),
+        None => (rest, None),
+    };
+
+    let (int_part, frac_part) = match mantissa.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+        None => (mantissa, None),
+    };
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    all_digits(int_part)
+        && frac_part.map_or(true, all_digits)
+        && exponent.map_or(true, |e| {
+            all_digits(e.strip_prefix(['+', '-']).unwrap_or(e))
+        })
+}
@@ -0,0 +1,142 @@
+//! Layered record sources, such as built-in defaults, a DNS TXT set, a local file and
+//! environment overrides.
+//!
+//! Layers are merged key by key, and later layers take precedence. An array in a later layer
+//! replaces the whole array of the earlier layers, so that no stale elements are left behind.
+//! The merged value comes with the name of the layer that supplied each record.
+//!
+//! ```rust
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Debug, PartialEq)]
+//! struct Server {
+//!     host: String,
+//!     port: u16,
+//!     tags: Vec<String>,
+//! }
+//!
+//! let records = |pairs: &[(&str, &str)]| -> serde_txtrecord::TxtRecords {
+//!     pairs.iter().copied().collect()
+//! };
+//!
+//! let server: serde_txtrecord::Layered<Server> = serde_txtrecord::from_layers(vec![
+//!     ("defaults", records(&[("host", "localhost"), ("port", "80"), ("tags_0", "a"), ("tags_1", "b"), ("tags_len", "2")])),
+//!     ("env", records(&[("port", "8080"), ("tags_0", "c"), ("tags_len", "1")])),
+//! ]).unwrap();
+//!
+//! assert_eq!(server.value, Server { host: "localhost".to_string(), port: 8080, tags: vec!["c".to_string()] });
+//! assert_eq!(server.source("host"), Some("defaults"));
+//! assert_eq!(server.source("port"), Some("env"));
+//! ```
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::{from_txt_records_with_config, DeserializeError, TxtRecordConfig, TxtRecords};
+
+/// A value merged from several layers, with the layer that supplied each record
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layered<T> {
+    pub value: T,
+    /// Name of the layer that supplied each record, by record key
+    pub provenance: BTreeMap<String, String>,
+}
+
+impl<T> Layered<T> {
+    /// Returns the name of the layer that supplied a record
+    ///
+    /// Arrays are supplied as a whole, so the source of an array is the source of its length
+    /// record, e.g. `tags_len`.
+    pub fn source(&self, key: &str) -> Option<&str> {
+        self.provenance.get(key).map(String::as_str)
+    }
+}
+
+/// Deserialize a value from `(name, records)` layers, where later layers take precedence
+pub fn from_layers<I, S, R, T>(layers: I) -> Result<Layered<T>, DeserializeError>
+where
+    I: IntoIterator<Item = (S, R)>,
+    S: Into<String>,
+    R: Into<TxtRecords>,
+    T: for<'de> Deserialize<'de>,
+{
+    from_layers_with_config(layers, TxtRecordConfig::default())
+}
+
+/// Deserialize a value from `(name, records)` layers with custom configuration
+pub fn from_layers_with_config<I, S, R, T>(
+    layers: I,
+    config: TxtRecordConfig,
+) -> Result<Layered<T>, DeserializeError>
+where
+    I: IntoIterator<Item = (S, R)>,
+    S: Into<String>,
+    R: Into<TxtRecords>,
+    T: for<'de> Deserialize<'de>,
+{
+    config.validate()?;
+    let (records, provenance) = merge_layers(layers, &config);
+    let value = from_txt_records_with_config(records, config)?;
+    Ok(Layered { value, provenance })
+}
+
+/// Merge `(name, records)` layers into one set of records, returning the records and the
+/// name of the layer that supplied each one
+///
+/// Keys are compared after applying [`TxtRecordConfig::key_case`]. When a layer contains the
+/// length record of an array, the elements and length of that array are removed from the
+/// earlier layers first.
+pub fn merge_layers<I, S, R>(
+    layers: I,
+    config: &TxtRecordConfig,
+) -> (TxtRecords, BTreeMap<String, String>)
+where
+    I: IntoIterator<Item = (S, R)>,
+    S: Into<String>,
+    R: Into<TxtRecords>,
+{
+    let namespace = config.namespace();
+    let codec = config.codec();
+
+    let mut merged = TxtRecords::new();
+    let mut provenance = BTreeMap::new();
+    for (name, records) in layers {
+        let name = name.into();
+        let records: TxtRecords = records
+            .into()
+            .into_iter()
+            .map(|(key, value)| (config.key_case.apply(&key).into_owned(), value))
+            .collect();
+
+        // arrays in this layer replace the arrays of the earlier layers
+        let exists = |key: &str| records.contains_key(&format!("{}{}", namespace, key));
+        let len_key = |base: &str| config.key_case.apply(&codec.len_key(base)).into_owned();
+        let mut arrays: Vec<String> = records
+            .keys()
+            .filter_map(|key| key.strip_prefix(namespace.as_str()))
+            .flat_map(|key| config.parent_keys(key, &exists))
+            .filter(|base| exists(&len_key(base)))
+            .collect();
+        arrays.sort_unstable();
+        arrays.dedup();
+        if !arrays.is_empty() {
+            merged.retain(|key, _| {
+                let stale = key.strip_prefix(namespace.as_str()).is_some_and(|key| {
+                    arrays
+                        .iter()
+                        .any(|base| key == len_key(base) || config.split_index(base, key).is_some())
+                });
+                if stale {
+                    provenance.remove(key);
+                }
+                !stale
+            });
+        }
+
+        for (key, value) in records {
+            provenance.insert(key.clone(), name.clone());
+            merged.insert(key, value);
+        }
+    }
+    (merged, provenance)
+}
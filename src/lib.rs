@@ -8,8 +8,52 @@
 //! - Simple key-value pairs: `key: value` → `key=value`
 //! - Arrays: `key: ["val", "bal"]` → `key_0=val, key_1=bal, key_len=2`
 //! - Objects: `key: { foo: "val", bar: "bal" }` → `key.foo=val, key.bar=bal`
-//! - Record length limits: Each `key=value` record can be limited to a maximum length (default: 255 characters)
+//! - Record length limits: Each `key=value` record can be limited to a maximum length (default: 255 bytes),
+//!   counted in bytes or characters with [`TxtRecordConfig::length_unit`], and optionally restricted to ASCII
 //! - Configurable separators and suffixes: Customize array separators, object separators, and array length suffixes
+//! - Namespaces: Write and read several values in one record set under
+//!   [`TxtRecordConfig::prefix`], optionally rejecting unknown keys with [`TxtRecordConfig::strict`]
+//! - Layered sources: Merge defaults, DNS records, files and overrides key by key with
+//!   [`from_layers`], keeping the source of each record, see the [`layered`] module
+//! - Diffs: Compare two record sets with [`diff()`](fn@diff) or two values with
+//!   [`diff_values`] to update only the records that changed, see the [`diff`](mod@diff) module
+//! - Deterministic output: Sort records lexicographically or naturally with
+//!   [`TxtRecordConfig::ordering`], so that maps produce the same records on every run
+//! - Record sets: [`TxtRecords`] keeps records in insertion order with unique keys, constant
+//!   time lookups and typed getters such as [`TxtRecords::get_as`]
+//! - Key schemas: List the key patterns a type is written to, with their Rust types, using
+//!   [`describe`], and render them as Markdown or JSON, see the [`schema`] module
+//! - Subtrees and selectors: Deserialize one part of a record set with [`from_txt_records_at`],
+//!   and pick records or values with wildcards such as `authors[*].email` using
+//!   [`TxtRecords::select`], see the [`select`] module
+//! - Typed paths: Address records with [`TxtPath`] and the [`path!`] macro instead of keys
+//!   that depend on the separators, e.g. [`TxtRecords::get_path`], see the [`path`](mod@path) module
+//! - Key codecs: Lay out the path of a value in its key with a [`KeyCodec`], such as
+//!   [`BracketCodec`], [`ZeroPaddedCodec`] or [`EscapingCodec`], see the [`codec`] module
+//! - Shape conflicts: Records that make one key both a value and an object or array, such as
+//!   `a=1` next to `a.b=2`, fail in strict mode and are returned as warnings by
+//!   [`from_txt_records_with_warnings`] otherwise
+//! - Key collision detection: Serialization fails when two values flatten to the same key, and
+//!   the error names the Rust path of both, e.g. `items[0]` and `items_0`
+//! - Round-trip checks: Find values that read back differently, such as enum variants with
+//!   data, and verify that a value survives the format, see the [`verify`] module
+//! - Overflow policies: Truncate, skip or replace records that are too long instead of failing,
+//!   and get back what was changed from [`to_txt_records_with_adjustments`]
+//! - Key dictionaries: Shorten long paths and fields to aliases when writing and expand them
+//!   when reading, with a dictionary that is given or derived and published as a record, see
+//!   the [`alias`] module
+//! - Size reports: See the length and headroom of every record and the totals of each object and
+//!   array with [`size_report`], see the [`size`] module
+//! - Flattening: `#[serde(flatten)]` structs read their fields from the parent prefix, and a
+//!   flattened catch-all map receives the records no other field took
+//! - Text format: One `key=value` record per line with [`to_string`], [`to_writer`], [`from_str`]
+//!   and [`from_reader`], see the [`text`] module
+//! - Environment variables: Read the environment with [`from_env`] and `.env` files with
+//!   [`to_dotenv`] and [`from_dotenv`], see the [`env`](mod@env) module
+//! - Query strings: Percent-encoded URL query strings and form bodies with [`to_query_string`]
+//!   and [`from_query_string`], see the [`query`] module
+//! - Dynamic values: Inspect and build records without a concrete type using [`TxtValue`]
+//! - Type inference: Optionally infer numbers, booleans and `null` for untyped targets, see [`TxtRecordConfig::infer_types`]
 //!
 //! ## Example
 //!
@@ -36,22 +80,65 @@
 //! assert_eq!(person, deserialized);
 //! ```
 
+pub mod alias;
+pub mod codec;
 pub mod config;
 pub mod de;
+pub mod diff;
+pub mod env;
+mod infer;
+pub mod layered;
+pub mod path;
+pub mod query;
+pub mod records;
+pub mod schema;
+pub mod select;
 pub mod ser;
+pub mod size;
+pub mod text;
+pub mod value;
+pub mod verify;
 
 // export main functionality
-pub use config::TxtRecordConfig;
+pub use alias::KeyDictionary;
+pub use codec::{BracketCodec, EscapingCodec, KeyCodec, Segment, ZeroPaddedCodec};
+pub use config::{
+    ConfigError, KeyCase, KeyOrder, LengthUnit, OverflowAction, OverflowCallback, OverflowPolicy,
+    TxtRecordConfig, TxtRecordConfigBuilder,
+};
 pub use de::{
-    from_txt_records, from_txt_records_with_config, DeserializeError, TxtRecordDeserializer,
+    from_txt_records, from_txt_records_at, from_txt_records_at_with_config,
+    from_txt_records_with_config, from_txt_records_with_warnings, DeserializeError, ShapeConflict,
+    TxtRecordDeserializer,
+};
+pub use diff::{diff, diff_values, diff_values_with_config, TxtDiff};
+pub use env::{
+    from_dotenv, from_dotenv_with_config, from_env, from_env_with_config, to_dotenv,
+    to_dotenv_with_config,
+};
+pub use layered::{from_layers, from_layers_with_config, Layered};
+pub use path::{PathSegment, TxtPath};
+pub use query::{from_query_string, to_query_string};
+pub use records::TxtRecords;
+pub use schema::{describe, describe_with_config, KeySchema};
+pub use select::{Selector, SelectorSegment};
+pub use ser::{
+    to_txt_records, to_txt_records_with_adjustments, to_txt_records_with_config, Adjustment, Lossy,
+    LossyKind, TxtRecordError, TxtRecordSerializer,
+};
+pub use size::{size_report, SizeReport};
+pub use text::{
+    from_reader, from_reader_with_config, from_str, from_str_with_config, to_string,
+    to_string_with_config, to_writer, to_writer_with_config,
 };
-pub use ser::{to_txt_records, to_txt_records_with_config, TxtRecordError, TxtRecordSerializer};
+pub use value::TxtValue;
+pub use verify::{check_lossy, verify_roundtrip, RoundtripError};
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde::{Deserialize, Serialize};
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
 
     #[derive(Serialize, Deserialize, Debug, PartialEq)]
     struct TestStruct {
@@ -65,6 +152,149 @@ mod tests {
         tags: Vec<String>,
     }
 
+    // the example structs from `examples/`, used to check that presets round-trip
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Author {
+        name: String,
+        email: Option<String>,
+        biography: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Publisher {
+        name: String,
+        location: String,
+        founded: u16,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Metadata {
+        pages: u32,
+        word_count: Option<u64>,
+        language: String,
+        isbn: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Book {
+        title: String,
+        authors: Vec<Author>,
+        publisher: Publisher,
+        publication_year: u16,
+        price: f64,
+        available: bool,
+        genres: Vec<String>,
+        metadata: Metadata,
+        awards: Option<Vec<String>>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Address {
+        street: String,
+        city: String,
+        country: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Contact {
+        email: String,
+        phone: String,
+        address: Address,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Company {
+        name: String,
+        headquarters: Address,
+        branch_office: Address,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Employee {
+        name: String,
+        id: u32,
+        contact: Contact,
+        company: Company,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Matrix {
+        name: String,
+        data: Vec<Vec<i32>>,
+        metadata: Vec<Vec<String>>,
+    }
+
+    fn sample_book() -> Book {
+        Book {
+            title: "The Rust Programming Language".to_string(),
+            authors: vec![
+                Author {
+                    name: "Steve Klabnik".to_string(),
+                    email: Some("steve@example.com".to_string()),
+                    biography: "Technical writer and Rust core team member".to_string(),
+                },
+                Author {
+                    name: "Carol Nichols".to_string(),
+                    email: None,
+                    biography: "Rust consultant and educator".to_string(),
+                },
+            ],
+            publisher: Publisher {
+                name: "No Starch Press".to_string(),
+                location: "San Francisco, CA".to_string(),
+                founded: 1994,
+            },
+            publication_year: 2018,
+            price: 39.95,
+            available: true,
+            genres: vec!["Programming".to_string(), "Computer Science".to_string()],
+            metadata: Metadata {
+                pages: 552,
+                word_count: Some(180000),
+                language: "English".to_string(),
+                isbn: "978-1-59327-828-1".to_string(),
+            },
+            awards: Some(vec!["Best Programming Book 2019".to_string()]),
+        }
+    }
+
+    fn sample_address(street: &str, city: &str) -> Address {
+        Address {
+            street: street.to_string(),
+            city: city.to_string(),
+            country: "USA".to_string(),
+        }
+    }
+
+    fn sample_employee() -> Employee {
+        Employee {
+            name: "John Doe".to_string(),
+            id: 12345,
+            contact: Contact {
+                email: "john.doe@example.com".to_string(),
+                phone: "+1-555-0123".to_string(),
+                address: sample_address("123 Main St", "New York"),
+            },
+            company: Company {
+                name: "Tech Corp".to_string(),
+                headquarters: sample_address("456 Corporate Blvd", "San Francisco"),
+                branch_office: sample_address("789 Branch Ave", "Austin"),
+            },
+        }
+    }
+
+    fn sample_matrix() -> Matrix {
+        Matrix {
+            name: "Identity Matrix".to_string(),
+            data: vec![vec![1, 0, 0], vec![0, 1, 0], vec![0, 0, 1]],
+            metadata: vec![
+                vec!["row1".to_string(), "3x3".to_string()],
+                vec!["row2".to_string(), "identity".to_string()],
+                vec!["row3".to_string(), "diagonal".to_string()],
+            ],
+        }
+    }
+
     #[test]
     fn test_simple_key_value_roundtrip() {
         let mut map = HashMap::new();
@@ -128,6 +358,7 @@ mod tests {
             object_separator: "/".to_string(),
             record_len: 255,
             array_len_suffix: "_len".to_string(),
+            ..Default::default()
         };
 
         let mut map = HashMap::new();
@@ -143,6 +374,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_numeric_types_roundtrip() {
         #[derive(Serialize, Deserialize, Debug, PartialEq)]
         struct Numbers {
@@ -239,6 +471,7 @@ mod tests {
             object_separator: ".".to_string(),
             record_len: 20, // Very short limit for testing
             array_len_suffix: "_len".to_string(),
+            ..Default::default()
         };
 
         let mut map = HashMap::new();
@@ -278,6 +511,7 @@ mod tests {
             object_separator: ".".to_string(),
             record_len: 255,
             array_len_suffix: ".count".to_string(), // Custom suffix
+            ..Default::default()
         };
 
         let mut map = HashMap::new();
@@ -317,6 +551,7 @@ mod tests {
             object_separator: ".".to_string(),
             record_len: 255,
             array_len_suffix: "_len".to_string(),
+            ..Default::default()
         };
 
         let config2 = TxtRecordConfig {
@@ -324,6 +559,7 @@ mod tests {
             object_separator: ".".to_string(),
             record_len: 255,
             array_len_suffix: ".size".to_string(),
+            ..Default::default()
         };
 
         let mut map = HashMap::new();
@@ -353,4 +589,2503 @@ mod tests {
 
         assert_eq!(result1, result2); // Both should produce the same logical result
     }
+
+    #[test]
+    fn test_non_string_map_keys_roundtrip() {
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        enum Color {
+            Red,
+            Green,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Keyed {
+            ports: HashMap<u16, String>,
+            flags: BTreeMap<bool, u8>,
+            colors: BTreeMap<Color, String>,
+            initials: BTreeMap<char, i32>,
+        }
+
+        let keyed = Keyed {
+            ports: HashMap::from([(80, "http".to_string()), (443, "https".to_string())]),
+            flags: BTreeMap::from([(true, 1), (false, 0)]),
+            colors: BTreeMap::from([
+                (Color::Red, "#f00".to_string()),
+                (Color::Green, "#0f0".to_string()),
+            ]),
+            initials: BTreeMap::from([('a', -1), ('z', 26)]),
+        };
+
+        let records = to_txt_records(&keyed).unwrap();
+        let records_map: HashMap<String, String> = records.iter().cloned().collect();
+        assert_eq!(records_map.get("ports.443"), Some(&"https".to_string()));
+        assert_eq!(records_map.get("colors.Red"), Some(&"#f00".to_string()));
+
+        let result: Keyed = from_txt_records(records).unwrap();
+        assert_eq!(keyed, result);
+    }
+
+    #[test]
+    fn test_invalid_map_keys() {
+        let mut map = HashMap::new();
+        map.insert((1u8, 2u8), "pair");
+        match to_txt_records(&map).unwrap_err() {
+            TxtRecordError::InvalidMapKey(_) => {}
+            err => panic!("Expected InvalidMapKey error, got {:?}", err),
+        }
+
+        #[derive(PartialEq, Eq, PartialOrd, Ord)]
+        struct Bytes(Vec<u8>);
+
+        impl Serialize for Bytes {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(&self.0)
+            }
+        }
+
+        let mut map = BTreeMap::new();
+        map.insert(Bytes(b"valid".to_vec()), "utf-8");
+        assert_eq!(to_txt_records(&map).unwrap().get("valid"), Some("utf-8"));
+        map.insert(Bytes(vec![0xff, 0xfe]), "lossy");
+        assert!(matches!(
+            to_txt_records(&map),
+            Err(TxtRecordError::InvalidMapKey(msg)) if msg.contains("not valid UTF-8")
+        ));
+
+        let mut map = HashMap::new();
+        map.insert(Some(1u8), "some");
+        map.insert(None, "none");
+        assert!(matches!(
+            to_txt_records(&map),
+            Err(TxtRecordError::InvalidMapKey(_))
+        ));
+
+        let records = vec![("eighty".to_string(), "http".to_string())];
+        match from_txt_records::<HashMap<u16, String>>(records).unwrap_err() {
+            DeserializeError::InvalidMapKey(msg) => assert!(msg.contains("eighty")),
+            err => panic!("Expected InvalidMapKey error, got {:?}", err),
+        }
+
+        let records = vec![("key".to_string(), "value".to_string())];
+        assert!(matches!(
+            from_txt_records::<HashMap<(u8, u8), String>>(records),
+            Err(DeserializeError::InvalidMapKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_type_inference_for_untyped_targets() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        #[serde(untagged)]
+        enum Untyped {
+            Null(()),
+            Bool(bool),
+            Unsigned(u64),
+            Signed(i64),
+            Float(f64),
+            Str(String),
+        }
+
+        let records = vec![
+            ("count".to_string(), "42".to_string()),
+            ("offset".to_string(), "-7".to_string()),
+            ("ratio".to_string(), "0.5".to_string()),
+            ("enabled".to_string(), "true".to_string()),
+            ("nothing".to_string(), "null".to_string()),
+            ("name".to_string(), "alice".to_string()),
+            ("zip".to_string(), "\"01234\"".to_string()),
+            ("special".to_string(), "NaN".to_string()),
+        ];
+
+        // without inference every leaf is a string
+        let result: HashMap<String, Untyped> = from_txt_records(records.clone()).unwrap();
+        assert_eq!(result["count"], Untyped::Str("42".to_string()));
+
+        let config = TxtRecordConfig {
+            infer_types: true,
+            ..Default::default()
+        };
+        let result: HashMap<String, Untyped> =
+            from_txt_records_with_config(records, config).unwrap();
+        assert_eq!(result["count"], Untyped::Unsigned(42));
+        assert_eq!(result["offset"], Untyped::Signed(-7));
+        assert_eq!(result["ratio"], Untyped::Float(0.5));
+        assert_eq!(result["enabled"], Untyped::Bool(true));
+        assert_eq!(result["nothing"], Untyped::Null(()));
+        assert_eq!(result["name"], Untyped::Str("alice".to_string()));
+        assert_eq!(result["zip"], Untyped::Str("01234".to_string()));
+        assert_eq!(result["special"], Untyped::Str("NaN".to_string()));
+    }
+
+    #[test]
+    fn test_type_inference_quotes_ambiguous_strings() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Mixed {
+            zip: String,
+            answer: String,
+            quoted: String,
+            port: u16,
+            tags: Vec<String>,
+            initial: char,
+        }
+
+        let config = TxtRecordConfig {
+            infer_types: true,
+            ..Default::default()
+        };
+        let mixed = Mixed {
+            zip: "01234".to_string(),
+            answer: "true".to_string(),
+            quoted: "\"hi\"".to_string(),
+            port: 8080,
+            tags: vec!["null".to_string(), "plain".to_string()],
+            initial: '7',
+        };
+
+        let records = to_txt_records_with_config(&mixed, config.clone()).unwrap();
+        let records_map: HashMap<String, String> = records.iter().cloned().collect();
+        assert_eq!(records_map.get("zip"), Some(&"\"01234\"".to_string()));
+        assert_eq!(records_map.get("answer"), Some(&"\"true\"".to_string()));
+        assert_eq!(records_map.get("quoted"), Some(&"\"\"hi\"\"".to_string()));
+        assert_eq!(records_map.get("port"), Some(&"8080".to_string()));
+        assert_eq!(records_map.get("tags_1"), Some(&"plain".to_string()));
+
+        let result: Mixed = from_txt_records_with_config(records, config).unwrap();
+        assert_eq!(mixed, result);
+    }
+
+    #[test]
+    fn test_map_keys_with_array_separator() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Labels {
+            labels: HashMap<String, String>,
+            sizes: HashMap<String, Vec<u32>>,
+        }
+
+        let labels = Labels {
+            labels: HashMap::from([
+                ("app_name".to_string(), "web".to_string()),
+                ("tier_2".to_string(), "backend".to_string()),
+            ]),
+            sizes: HashMap::from([("small_set".to_string(), vec![1, 2])]),
+        };
+
+        let records = to_txt_records(&labels).unwrap();
+        let result: Labels = from_txt_records(records.clone()).unwrap();
+        assert_eq!(labels, result);
+
+        // names containing the array separator are only arrays if they have a length record
+        let root: HashMap<String, String> = from_txt_records(vec![
+            ("publication_year".to_string(), "2018".to_string()),
+            ("page_2".to_string(), "appendix".to_string()),
+        ])
+        .unwrap();
+        assert_eq!(root.get("publication_year"), Some(&"2018".to_string()));
+        assert_eq!(root.get("page_2"), Some(&"appendix".to_string()));
+    }
+
+    #[test]
+    fn test_flatten_roundtrip() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Connection {
+            host: String,
+            user_name: String,
+            replicas: Vec<String>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Service {
+            name: String,
+            #[serde(flatten)]
+            connection: Connection,
+            #[serde(flatten)]
+            extra: HashMap<String, String>,
+        }
+
+        let records = vec![
+            ("name".to_string(), "db".to_string()),
+            ("host".to_string(), "localhost".to_string()),
+            ("user_name".to_string(), "admin".to_string()),
+            ("replicas_0".to_string(), "r1".to_string()),
+            ("replicas_1".to_string(), "r2".to_string()),
+            ("replicas_len".to_string(), "2".to_string()),
+            ("x_vendor".to_string(), "acme".to_string()),
+            ("x_vendor_id".to_string(), "42".to_string()),
+            ("x_flag".to_string(), "true".to_string()),
+        ];
+
+        let service: Service = from_txt_records(records.clone()).unwrap();
+        assert_eq!(service.connection.user_name, "admin");
+        assert_eq!(service.connection.replicas, vec!["r1", "r2"]);
+        assert_eq!(
+            service.extra,
+            HashMap::from([
+                ("x_vendor".to_string(), "acme".to_string()),
+                ("x_vendor_id".to_string(), "42".to_string()),
+                ("x_flag".to_string(), "true".to_string()),
+            ])
+        );
+
+        // unknown vendor keys are kept when written back out
+        let mut written = to_txt_records(&service).unwrap();
+        let mut expected = records;
+        written.sort();
+        expected.sort();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_flatten_with_typed_leaves() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Endpoint {
+            host: String,
+            port: u16,
+            secure: bool,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Cache {
+            name: String,
+            #[serde(flatten)]
+            endpoint: Endpoint,
+        }
+
+        let cache = Cache {
+            name: "redis".to_string(),
+            endpoint: Endpoint {
+                host: "10".to_string(),
+                port: 6379,
+                secure: false,
+            },
+        };
+
+        // flattened fields are buffered without their type, so they need inference
+        let config = TxtRecordConfig {
+            infer_types: true,
+            ..Default::default()
+        };
+        let records = to_txt_records_with_config(&cache, config.clone()).unwrap();
+        assert_eq!(records.get("host"), Some("\"10\""));
+        let result: Cache = from_txt_records_with_config(records, config).unwrap();
+        assert_eq!(cache, result);
+    }
+
+    #[test]
+    fn test_flatten_catch_all_roundtrip() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Service {
+            name: String,
+            #[serde(flatten)]
+            extra: HashMap<String, String>,
+        }
+
+        let service = Service {
+            name: "db".to_string(),
+            extra: HashMap::from([
+                ("build".to_string(), "1234".to_string()),
+                ("beta".to_string(), "true".to_string()),
+                ("ratio".to_string(), "0.5".to_string()),
+            ]),
+        };
+        let records = to_txt_records(&service).unwrap();
+        assert_eq!(records.get("build"), Some("1234"));
+        let result: Service = from_txt_records(records).unwrap();
+        assert_eq!(service, result);
+    }
+
+    #[test]
+    fn test_text_format_roundtrip() {
+        let mut map = HashMap::new();
+        map.insert("multi=line", "first\nsecond\\third");
+        map.insert("#hash", "a=b");
+
+        let text = to_string(&map).unwrap();
+        assert!(text.contains("multi\\=line=first\\nsecond\\\\third\n"));
+        assert!(text.contains("\\#hash=a=b\n"));
+
+        let result: HashMap<String, String> = from_str(&text).unwrap();
+        let expected: HashMap<String, String> = map
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_text_format_comments_and_reader() {
+        let input = "# service definition\n\nname=Alice\r\n  # indented comment\nage=30\n";
+        let person: TestStruct = from_reader(input.as_bytes()).unwrap();
+        assert_eq!(
+            person,
+            TestStruct {
+                name: "Alice".to_string(),
+                age: 30
+            }
+        );
+
+        let mut output = Vec::new();
+        to_writer(&mut output, &person).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "name=Alice\nage=30\n");
+
+        match from_str::<TestStruct>("name=Alice\nage 30\n").unwrap_err() {
+            DeserializeError::InvalidFormat(msg) => assert!(msg.starts_with("line 2")),
+            err => panic!("Expected InvalidFormat error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_custom_assignment_delimiter() {
+        let config = TxtRecordConfig {
+            assignment_delimiter: ": ".to_string(),
+            record_len: 10,
+            ..Default::default()
+        };
+
+        let person = TestStruct {
+            name: "Bob".to_string(),
+            age: 25,
+        };
+        let text = to_string_with_config(&person, config.clone()).unwrap();
+        assert_eq!(text, "name: Bob\nage: 25\n");
+        let result: TestStruct = from_str_with_config(&text, config.clone()).unwrap();
+        assert_eq!(person, result);
+
+        // the delimiter counts towards the record length
+        let long_name = TestStruct {
+            name: "Carol".to_string(),
+            age: 25,
+        };
+        match to_txt_records_with_config(&long_name, config).unwrap_err() {
+            TxtRecordError::RecordTooLong { actual_len, .. } => assert_eq!(actual_len, 11),
+            err => panic!("Expected RecordTooLong error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_txt_value_from_records() {
+        let records = vec![
+            ("app_name".to_string(), "demo".to_string()),
+            ("services.cache.host".to_string(), "localhost".to_string()),
+            ("services.cache.port".to_string(), "6379".to_string()),
+            ("tags_0".to_string(), "a".to_string()),
+            ("tags_1".to_string(), "b".to_string()),
+            ("tags_len".to_string(), "2".to_string()),
+        ];
+
+        let value = TxtValue::from_records(records.clone()).unwrap();
+        assert_eq!(value["app_name"].as_str(), Some("demo"));
+        assert_eq!(value["services"]["cache"]["port"].as_u64(), Some(6379));
+        assert_eq!(value["tags"][1], TxtValue::from("b"));
+        assert!(value["tags"][2].is_null());
+        assert!(value["missing"]["deeper"].is_null());
+
+        assert_eq!(
+            value.to_string(),
+            "app_name: demo\n\
+             services:\n\
+             \x20 cache:\n\
+             \x20   host: localhost\n\
+             \x20   port: 6379\n\
+             tags:\n\
+             \x20 [0]: a\n\
+             \x20 [1]: b\n"
+        );
+
+        let mut written = value.to_records().unwrap();
+        let mut expected = records;
+        written.sort();
+        expected.sort();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_txt_value_typed_roundtrip() {
+        let mut value = TxtValue::default();
+        value["name"] = "server".into();
+        value["port"] = 8080u16.into();
+        value["ratio"] = 0.25.into();
+        value["enabled"] = true.into();
+        value["zip"] = "01234".into();
+        value["ports"] = vec![80u16, 443].into();
+        value["limits"]["big"] = TxtValue::Integer(i128::from(u64::MAX) + 1);
+        value["xs"] = TxtValue::Array(vec![TxtValue::Null, "a".into(), TxtValue::Null]);
+
+        // null elements write no records, and are read back from their place below the length
+        let records = value.to_records().unwrap();
+        assert_eq!(records.get("xs_0"), None);
+        assert_eq!(records.get("xs_len"), Some("3"));
+        let result = TxtValue::from_records(records).unwrap();
+        assert_eq!(result["xs"], value["xs"]);
+
+        let config = TxtRecordConfig {
+            infer_types: true,
+            ..Default::default()
+        };
+        let records = value.to_records_with_config(config.clone()).unwrap();
+        let result = TxtValue::from_records_with_config(records, config).unwrap();
+        assert_eq!(value["name"], result["name"]);
+        assert_eq!(value["port"], result["port"]);
+        assert_eq!(value["ratio"], result["ratio"]);
+        assert_eq!(value["enabled"], result["enabled"]);
+        assert_eq!(value["zip"], result["zip"]);
+        assert_eq!(value["ports"], result["ports"]);
+        assert_eq!(value["xs"], result["xs"]);
+        // integers beyond 64 bits are read back as floats by inference
+        assert!(result["limits"]["big"].as_f64().is_some());
+
+        // a typed value can also be read from a tree
+        let person: TestStruct = from_txt_records(
+            TxtValue::from_iter([("name", TxtValue::from("Alice")), ("age", 30.into())])
+                .to_records()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(person.age, 30);
+
+        // values other than objects and arrays have no key at the root
+        for root in [TxtValue::from(5u8), "x".into(), TxtValue::Null] {
+            assert!(matches!(
+                root.to_records(),
+                Err(TxtRecordError::UnsupportedType(_))
+            ));
+        }
+        let config = TxtRecordConfig::builder().prefix("app").build().unwrap();
+        let records = TxtValue::from(5u8).to_records_with_config(config).unwrap();
+        assert_eq!(records.get("app"), Some("5"));
+        let records = TxtValue::from(vec!["a"]).to_records().unwrap();
+        assert_eq!(records.get("_0"), Some("a"));
+    }
+
+    #[test]
+    fn test_config_builder_validation() {
+        let config = TxtRecordConfig::builder()
+            .array_separator("-")
+            .object_separator("/")
+            .array_len_suffix("-count")
+            .build()
+            .unwrap();
+        assert_eq!(config.array_separator, "-");
+
+        let invalid = [
+            (
+                TxtRecordConfig::builder().array_separator(""),
+                ConfigError::EmptyArraySeparator,
+            ),
+            (
+                TxtRecordConfig::builder().object_separator("_"),
+                ConfigError::SameSeparators("_".to_string()),
+            ),
+            (
+                TxtRecordConfig::builder().array_len_suffix("_2"),
+                ConfigError::AmbiguousArrayLenSuffix {
+                    array_len_suffix: "_2".to_string(),
+                    array_separator: "_".to_string(),
+                },
+            ),
+            (
+                TxtRecordConfig::builder().record_len(1),
+                ConfigError::RecordLenTooSmall {
+                    record_len: 1,
+                    min_len: 2,
+                },
+            ),
+        ];
+        for (builder, expected) in invalid {
+            assert_eq!(builder.build().unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn test_config_validated_by_top_level_functions() {
+        let config = TxtRecordConfig {
+            object_separator: "_".to_string(),
+            ..Default::default()
+        };
+        let person = TestStruct {
+            name: "Alice".to_string(),
+            age: 30,
+        };
+
+        assert!(matches!(
+            to_txt_records_with_config(&person, config.clone()),
+            Err(TxtRecordError::Config(ConfigError::SameSeparators(_)))
+        ));
+        assert!(matches!(
+            from_txt_records_with_config::<TestStruct>(vec![], config),
+            Err(DeserializeError::Config(ConfigError::SameSeparators(_)))
+        ));
+
+        // a key that can never fit is explained in the error message
+        let config = TxtRecordConfig::builder().record_len(4).build().unwrap();
+        let err = to_txt_records_with_config(&person, config).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("record_len is too small for this data"));
+    }
+
+    fn assert_preset_roundtrip(config: TxtRecordConfig) -> TxtRecords {
+        config.validate().unwrap();
+
+        let book = sample_book();
+        let records = to_txt_records_with_config(&book, config.clone()).unwrap();
+        let result: Book = from_txt_records_with_config(records.clone(), config.clone()).unwrap();
+        assert_eq!(book, result);
+
+        let employee = sample_employee();
+        let employee_records = to_txt_records_with_config(&employee, config.clone()).unwrap();
+        let result: Employee =
+            from_txt_records_with_config(employee_records, config.clone()).unwrap();
+        assert_eq!(employee, result);
+
+        let matrix = sample_matrix();
+        let matrix_records = to_txt_records_with_config(&matrix, config.clone()).unwrap();
+        let result: Matrix = from_txt_records_with_config(matrix_records, config).unwrap();
+        assert_eq!(matrix, result);
+
+        records
+    }
+
+    #[test]
+    fn test_dns_sd_preset() {
+        let records: HashMap<String, String> = assert_preset_roundtrip(TxtRecordConfig::dns_sd())
+            .into_iter()
+            .collect();
+        assert_eq!(records.get("genres#"), Some(&"2".to_string()));
+        assert_eq!(records.get("metadata.pages"), Some(&"552".to_string()));
+
+        let map = HashMap::from([("café", "open")]);
+        assert!(matches!(
+            to_txt_records_with_config(&map, TxtRecordConfig::dns_sd()),
+            Err(TxtRecordError::NonAscii {
+                character: 'é', ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_dns_preset() {
+        let records: HashMap<String, String> = assert_preset_roundtrip(TxtRecordConfig::dns())
+            .into_iter()
+            .collect();
+        assert_eq!(records.get("authors_len"), Some(&"2".to_string()));
+    }
+
+    #[test]
+    fn test_env_preset() {
+        let records: HashMap<String, String> = assert_preset_roundtrip(TxtRecordConfig::env())
+            .into_iter()
+            .collect();
+        assert_eq!(records.get("PUBLICATION_YEAR"), Some(&"2018".to_string()));
+        assert_eq!(
+            records.get("AUTHORS_0__NAME"),
+            Some(&"Steve Klabnik".to_string())
+        );
+        assert_eq!(records.get("GENRES_LEN"), Some(&"2".to_string()));
+
+        // keys are matched without regard to case
+        let records = vec![
+            ("Name".to_string(), "Alice".to_string()),
+            ("age".to_string(), "30".to_string()),
+        ];
+        let person: TestStruct =
+            from_txt_records_with_config(records, TxtRecordConfig::env()).unwrap();
+        assert_eq!(person.name, "Alice");
+    }
+
+    #[test]
+    fn test_kv_path_preset() {
+        let records: HashMap<String, String> = assert_preset_roundtrip(TxtRecordConfig::kv_path())
+            .into_iter()
+            .collect();
+        assert_eq!(
+            records.get("metadata/isbn"),
+            Some(&"978-1-59327-828-1".to_string())
+        );
+        assert_eq!(
+            records.get("authors_0/email"),
+            Some(&"steve@example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn test_query_string_preset() {
+        let records: HashMap<String, String> =
+            assert_preset_roundtrip(TxtRecordConfig::query_string())
+                .into_iter()
+                .collect();
+        assert_eq!(records.get("awards_len"), Some(&"1".to_string()));
+    }
+
+    #[test]
+    fn test_from_env_vars() {
+        let vars = [
+            ("TXTRECORD_TEST_NAME", "Alice"),
+            ("TXTRECORD_TEST_AGE", "30"),
+            ("TXTRECORD_OTHER_NAME", "Bob"),
+        ];
+        let person: TestStruct =
+            env::from_vars_with_config(vars, "TXTRECORD_TEST_", TxtRecordConfig::env()).unwrap();
+        assert_eq!(
+            person,
+            TestStruct {
+                name: "Alice".to_string(),
+                age: 30
+            }
+        );
+
+        // names are matched without regard to case, arrays and nesting work as for TXT
+        let vars = vec![
+            ("app_person__Name".to_string(), "Bob".to_string()),
+            ("APP_PERSON__AGE".to_string(), "25".to_string()),
+            ("APP_TAGS_0".to_string(), "developer".to_string()),
+            ("app_tags_len".to_string(), "1".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ];
+        let nested: NestedStruct =
+            env::from_vars_with_config(vars, "APP_", TxtRecordConfig::env()).unwrap();
+        assert_eq!(nested.person.name, "Bob");
+        assert_eq!(nested.tags, vec!["developer"]);
+
+        // variables that are not UTF-8 are skipped outside the prefix and rejected inside it
+        #[cfg(unix)]
+        {
+            use std::ffi::OsString;
+            use std::os::unix::ffi::OsStringExt;
+
+            let invalid = || OsString::from_vec(vec![0x66, 0x6f, 0xff]);
+            let vars = vec![
+                (OsString::from("APP_NAME"), OsString::from("Alice")),
+                (OsString::from("APP_AGE"), OsString::from("30")),
+                (invalid(), OsString::from("ignored")),
+            ];
+            let person: TestStruct =
+                env::from_vars_with_config(vars.clone(), "APP_", TxtRecordConfig::env()).unwrap();
+            assert_eq!(person.name, "Alice");
+
+            let mut vars = vars;
+            vars.push((OsString::from("APP_EXTRA"), invalid()));
+            assert!(matches!(
+                env::from_vars_with_config::<_, _, _, TestStruct>(vars, "APP_", TxtRecordConfig::env()),
+                Err(DeserializeError::InvalidValue(msg)) if msg.contains("APP_EXTRA")
+            ));
+        }
+    }
+
+    #[test]
+    fn test_dotenv_roundtrip() {
+        let employee = sample_employee();
+        let mut dotenv = to_dotenv(&employee).unwrap();
+        assert!(dotenv.contains("CONTACT__PHONE=+1-555-0123\n"));
+        assert!(dotenv.contains("COMPANY__HEADQUARTERS__STREET=\"456 Corporate Blvd\"\n"));
+        dotenv.push_str("# trailing comment\n");
+        let result: Employee = from_dotenv(&dotenv).unwrap();
+        assert_eq!(employee, result);
+
+        let mut map = HashMap::new();
+        map.insert("QUOTED", "say \"hi\" to $USER\\n");
+        map.insert("MULTI", "first\nsecond");
+        map.insert("EMPTY", "");
+        let dotenv = to_dotenv(&map).unwrap();
+        let result: HashMap<String, String> = from_dotenv(&dotenv).unwrap();
+        let expected: HashMap<String, String> = map
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(result, expected);
+
+        let input = "export NAME='single $quoted'\nAGE=30 # inline comment\n";
+        let person: TestStruct = from_dotenv(input).unwrap();
+        assert_eq!(person.name, "single $quoted");
+        assert_eq!(person.age, 30);
+    }
+
+    #[test]
+    fn test_query_string_roundtrip() {
+        let book = sample_book();
+        let query = to_query_string(&book).unwrap();
+        assert!(query.contains("authors_0.name=Steve%20Klabnik"));
+        assert!(query.contains("authors_len=2"));
+        let result: Book = from_query_string(&format!("?{}", query)).unwrap();
+        assert_eq!(book, result);
+
+        let mut map = HashMap::new();
+        map.insert("a&b=c".to_string(), "100% sure?+ünïcode".to_string());
+        let query = to_query_string(&map).unwrap();
+        assert_eq!(query, "a%26b%3Dc=100%25%20sure%3F%2B%C3%BCn%C3%AFcode");
+        let result: HashMap<String, String> = from_query_string(&query).unwrap();
+        assert_eq!(map, result);
+
+        assert!(matches!(
+            from_query_string::<HashMap<String, String>>("a=%zz"),
+            Err(DeserializeError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_query_string_form_encoding() {
+        let config = query::QueryConfig {
+            form_encoding: true,
+            ..Default::default()
+        };
+        let person = TestStruct {
+            name: "Ada Lovelace+1".to_string(),
+            age: 36,
+        };
+        let query = query::to_query_string_with_config(&person, &config).unwrap();
+        assert_eq!(query, "name=Ada+Lovelace%2B1&age=36");
+        let result: TestStruct = query::from_query_string_with_config(&query, &config).unwrap();
+        assert_eq!(person, result);
+
+        // without form encoding, `+` is a literal plus
+        let result: TestStruct = from_query_string("name=Ada+Lovelace&age=36").unwrap();
+        assert_eq!(result.name, "Ada+Lovelace");
+    }
+
+    #[test]
+    fn test_query_string_bracket_keys() {
+        let config = query::QueryConfig {
+            bracket_keys: true,
+            ..Default::default()
+        };
+        let book = sample_book();
+        let query = query::to_query_string_with_config(&book, &config).unwrap();
+        assert!(query.contains("authors%5B1%5D%5Bname%5D=Carol%20Nichols"));
+        assert!(query.contains("authors%5Blen%5D=2"));
+        let result: Book = query::from_query_string_with_config(&query, &config).unwrap();
+        assert_eq!(book, result);
+
+        let matrix = sample_matrix();
+        let query = query::to_query_string_with_config(&matrix, &config).unwrap();
+        let result: Matrix = query::from_query_string_with_config(&query, &config).unwrap();
+        assert_eq!(matrix, result);
+
+        // numeric map keys and field names with digits are not array indices
+        let mut ports = HashMap::new();
+        ports.insert("80".to_string(), "http".to_string());
+        ports.insert("page_2".to_string(), "next".to_string());
+        let query = query::to_query_string_with_config(&ports, &config).unwrap();
+        assert!(query.contains("page_2=next"));
+        let result: HashMap<String, String> =
+            query::from_query_string_with_config(&query, &config).unwrap();
+        assert_eq!(ports, result);
+
+        // a field named `len` is not an array length
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Range {
+            start: u32,
+            len: u32,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Selection {
+            range: Range,
+            ranges: Vec<Range>,
+            empty: Vec<u32>,
+        }
+
+        let selection = Selection {
+            range: Range { start: 2, len: 5 },
+            ranges: vec![Range { start: 0, len: 1 }],
+            empty: Vec::new(),
+        };
+        let query = query::to_query_string_with_config(&selection, &config).unwrap();
+        assert!(query.contains("range%5Blen%5D=5"));
+        assert!(query.contains("ranges%5B0%5D%5Blen%5D=1"));
+        assert!(query.contains("empty%5Blen%5D=0"));
+        let result: Selection = query::from_query_string_with_config(&query, &config).unwrap();
+        assert_eq!(selection, result);
+    }
+
+    #[test]
+    fn test_prefixed_record_sets() {
+        let svc = TxtRecordConfig::builder().prefix("svc").build().unwrap();
+        let meta = TxtRecordConfig::builder().prefix("meta").build().unwrap();
+
+        let person = TestStruct {
+            name: "api".to_string(),
+            age: 3,
+        };
+        let nested = NestedStruct {
+            person: TestStruct {
+                name: "ops".to_string(),
+                age: 40,
+            },
+            tags: vec!["prod".to_string()],
+        };
+        let mut records = to_txt_records_with_config(&person, svc.clone()).unwrap();
+        assert_eq!(records[0], ("svc.name".to_string(), "api".to_string()));
+        records.extend(to_txt_records_with_config(&nested, meta.clone()).unwrap());
+        assert!(records.contains(&("meta.tags_len".to_string(), "1".to_string())));
+        records.insert("other", "ignored");
+
+        let result: TestStruct = from_txt_records_with_config(records.clone(), svc).unwrap();
+        assert_eq!(person, result);
+        let result: NestedStruct = from_txt_records_with_config(records.clone(), meta).unwrap();
+        assert_eq!(nested, result);
+
+        // maps only receive the records in their namespace, without the prefix
+        let config = TxtRecordConfig {
+            prefix: Some("svc".to_string()),
+            ..Default::default()
+        };
+        let map: HashMap<String, String> = from_txt_records_with_config(records, config).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["name"], "api");
+
+        // a scalar is written at the prefix itself
+        let config = TxtRecordConfig::builder().prefix("ver").build().unwrap();
+        let records = to_txt_records_with_config(&7u8, config.clone()).unwrap();
+        assert_eq!(records, vec![("ver".to_string(), "7".to_string())]);
+        let result: u8 = from_txt_records_with_config(records, config).unwrap();
+        assert_eq!(result, 7);
+
+        assert!(matches!(
+            TxtRecordConfig::builder().prefix("").build(),
+            Err(ConfigError::EmptyPrefix)
+        ));
+    }
+
+    #[test]
+    fn test_strict_mode_in_namespace() {
+        let config = TxtRecordConfig::builder()
+            .prefix("svc")
+            .strict(true)
+            .build()
+            .unwrap();
+        let mut records = vec![
+            ("svc.name".to_string(), "api".to_string()),
+            ("svc.age".to_string(), "3".to_string()),
+            ("meta.owner".to_string(), "ops".to_string()),
+        ];
+        let result: TestStruct = from_txt_records_with_config(records.clone(), config.clone())
+            .expect("records outside the namespace are not unknown");
+        assert_eq!(result.name, "api");
+
+        records.push(("svc.port".to_string(), "80".to_string()));
+        records.push(("svc.extra.deep".to_string(), "x".to_string()));
+        match from_txt_records_with_config::<TestStruct>(records.clone(), config) {
+            Err(DeserializeError::UnknownKeys(keys)) => {
+                assert_eq!(keys, vec!["svc.extra.deep", "svc.port"])
+            }
+            other => panic!("expected unknown keys, got {:?}", other),
+        }
+
+        // without strict mode, unknown keys are ignored as before
+        let config = TxtRecordConfig::builder().prefix("svc").build().unwrap();
+        assert!(from_txt_records_with_config::<TestStruct>(records, config).is_ok());
+
+        // arrays, options and maps mark the records they read
+        let strict = TxtRecordConfig {
+            strict: true,
+            ..Default::default()
+        };
+        let book = sample_book();
+        let records = to_txt_records_with_config(&book, strict.clone()).unwrap();
+        let result: Book = from_txt_records_with_config(records, strict).unwrap();
+        assert_eq!(book, result);
+    }
+
+    fn to_records(pairs: &[(&str, &str)]) -> TxtRecords {
+        pairs.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_layered_sources_with_provenance() {
+        let book = sample_book();
+        let defaults = to_txt_records(&book).unwrap();
+        let dns = to_records(&[("publisher.name", "O'Reilly"), ("price", "45.5")]);
+        let file = to_records(&[("metadata.pages", "600")]);
+        let env = to_records(&[("price", "49.99"), ("available", "false")]);
+
+        let layered: Layered<Book> = from_layers(vec![
+            ("defaults", defaults),
+            ("dns", dns),
+            ("file", file),
+            ("env", env),
+        ])
+        .unwrap();
+        assert_eq!(layered.value.publisher.name, "O'Reilly");
+        assert_eq!(layered.value.publisher.location, book.publisher.location);
+        assert_eq!(layered.value.price, 49.99);
+        assert!(!layered.value.available);
+        assert_eq!(layered.value.metadata.pages, 600);
+        assert_eq!(layered.value.authors, book.authors);
+
+        assert_eq!(layered.source("publisher.name"), Some("dns"));
+        assert_eq!(layered.source("publisher.location"), Some("defaults"));
+        assert_eq!(layered.source("price"), Some("env"));
+        assert_eq!(layered.source("metadata.pages"), Some("file"));
+        assert_eq!(layered.source("missing"), None);
+    }
+
+    #[test]
+    fn test_layered_array_replacement() {
+        let book = sample_book();
+        let defaults = to_txt_records(&book).unwrap();
+        let overrides = to_records(&[
+            ("authors_0.name", "Ferris"),
+            ("authors_0.biography", "Crab"),
+            ("authors_len", "1"),
+            ("genres_len", "0"),
+        ]);
+
+        let (records, provenance) = layered::merge_layers(
+            vec![("defaults", defaults), ("overrides", overrides)],
+            &TxtRecordConfig::default(),
+        );
+        // the second author and the email of the first one are not carried over
+        assert!(!records.iter().any(|(key, _)| key.starts_with("authors_1")));
+        assert!(!records.iter().any(|(key, _)| key == "authors_0.email"));
+        assert!(!records
+            .iter()
+            .any(|(key, _)| key.starts_with("genres_") && key != "genres_len"));
+        assert!(!provenance.contains_key("authors_1.name"));
+        assert_eq!(provenance["authors_len"], "overrides");
+
+        let result: Book = from_txt_records(records).unwrap();
+        assert_eq!(result.authors.len(), 1);
+        assert_eq!(result.authors[0].name, "Ferris");
+        assert_eq!(result.authors[0].email, None);
+        assert!(result.genres.is_empty());
+        assert_eq!(result.title, book.title);
+
+        // nested arrays are replaced as a whole as well
+        let matrix = sample_matrix();
+        let defaults = to_txt_records(&matrix).unwrap();
+        let overrides = to_records(&[("data_0_0", "7"), ("data_0_len", "1"), ("data_len", "1")]);
+        let layered: Layered<Matrix> =
+            from_layers(vec![("defaults", defaults), ("overrides", overrides)]).unwrap();
+        assert_eq!(layered.value.data, vec![vec![7]]);
+        assert_eq!(layered.value.metadata, matrix.metadata);
+    }
+
+    #[test]
+    fn test_diff_record_sets() {
+        let old = sample_book();
+        let mut new = sample_book();
+        new.authors.pop();
+        new.authors[0].email = None;
+        new.genres.push("Systems".to_string());
+        new.price = 42.0;
+
+        let diff = diff_values(&old, &new).unwrap();
+        let removed: Vec<&str> = diff.removed.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(
+            removed,
+            vec!["authors_0.email", "authors_1.name", "authors_1.biography",]
+        );
+        assert_eq!(
+            diff.added,
+            vec![("genres_2".to_string(), "Systems".to_string())]
+        );
+        let changed: Vec<(&str, &str, &str)> = diff
+            .changed
+            .iter()
+            .map(|c| (c.key.as_str(), c.old.as_str(), c.new.as_str()))
+            .collect();
+        assert_eq!(
+            changed,
+            vec![
+                ("authors_len", "2", "1"),
+                ("price", "39.95", "42"),
+                ("genres_len", "2", "3"),
+            ]
+        );
+        assert_eq!(diff.len(), 7);
+
+        let mut records = to_txt_records(&old).unwrap();
+        diff::apply(&mut records, &diff);
+        let result: Book = from_txt_records(records).unwrap();
+        assert_eq!(new, result);
+
+        assert!(diff_values(&new, &new).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diff_apply_with_config() {
+        let config = TxtRecordConfig::env();
+        let old = sample_matrix();
+        let mut new = sample_matrix();
+        new.data.truncate(1);
+        new.data[0].push(9);
+
+        let diff = diff::diff_values_with_config(&old, &new, config.clone()).unwrap();
+        assert!(diff.removed.iter().any(|(key, _)| key == "DATA_2_LEN"));
+        assert!(diff
+            .added
+            .contains(&("DATA_0_3".to_string(), "9".to_string())));
+
+        let mut records = to_txt_records_with_config(&old, config.clone()).unwrap();
+        diff::apply(&mut records, &diff);
+        assert_eq!(
+            records.len(),
+            to_txt_records_with_config(&new, config.clone())
+                .unwrap()
+                .len()
+        );
+        let result: Matrix = from_txt_records_with_config(records, config).unwrap();
+        assert_eq!(new, result);
+
+        // the reverse diff restores the old records
+        let old_records = to_txt_records(&old).unwrap();
+        let new_records = to_txt_records(&new).unwrap();
+        let mut records = new_records.clone();
+        diff::apply(&mut records, &diff::diff(&new_records, &old_records));
+        let result: Matrix = from_txt_records(records).unwrap();
+        assert_eq!(old, result);
+    }
+
+    #[test]
+    fn test_record_ordering() {
+        let mut map = HashMap::new();
+        for i in 0..12 {
+            map.insert(format!("k{}", i), vec![i; 11]);
+        }
+
+        let lexicographic = TxtRecordConfig::builder()
+            .ordering(KeyOrder::Lexicographic)
+            .build()
+            .unwrap();
+        let records = to_txt_records_with_config(&map, lexicographic.clone()).unwrap();
+        let keys: Vec<&str> = records.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(&keys[..3], &["k0_0", "k0_1", "k0_10"]);
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+
+        let natural = TxtRecordConfig::builder()
+            .ordering(KeyOrder::Natural)
+            .build()
+            .unwrap();
+        let records = to_txt_records_with_config(&map, natural.clone()).unwrap();
+        let keys: Vec<&str> = records.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(&keys[..3], &["k0_0", "k0_1", "k0_2"]);
+        assert_eq!(&keys[10..12], &["k0_10", "k0_len"]);
+        assert_eq!(keys[12], "k1_0");
+        assert_eq!(keys[keys.len() - 1], "k11_len");
+
+        // the same value always produces the same records, whatever the map's hasher
+        for _ in 0..5 {
+            let map: HashMap<String, Vec<usize>> = map.clone().into_iter().collect();
+            assert_eq!(
+                records,
+                to_txt_records_with_config(&map, natural.clone()).unwrap()
+            );
+        }
+        let result: HashMap<String, Vec<usize>> =
+            from_txt_records_with_config(records, natural).unwrap();
+        assert_eq!(map, result);
+    }
+
+    #[test]
+    fn test_natural_ordering_edge_cases() {
+        let mut records: Vec<(String, String)> =
+            ["a_010", "a_9", "a_09", "a_len", "a", "a_1.b", "a_1"]
+                .iter()
+                .map(|key| (key.to_string(), String::new()))
+                .collect();
+        KeyOrder::Natural.sort(&mut records);
+        let keys: Vec<&str> = records.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec!["a", "a_1", "a_1.b", "a_09", "a_9", "a_010", "a_len"]
+        );
+
+        // numbers longer than any integer type still compare by value
+        let mut records = vec![
+            ("n_100000000000000000000000".to_string(), String::new()),
+            ("n_99999999999999999999999".to_string(), String::new()),
+        ];
+        KeyOrder::Natural.sort(&mut records);
+        assert_eq!(records[0].0, "n_99999999999999999999999");
+
+        // the default keeps the serialization order
+        let records = to_txt_records(&sample_book()).unwrap();
+        assert_eq!(records[0].0, "title");
+    }
+
+    #[test]
+    fn test_txt_records_collection() {
+        let mut records = to_txt_records(&sample_book()).unwrap();
+        assert_eq!(records.get("publisher.founded"), Some("1994"));
+        assert_eq!(records.get("publisher.missing"), None);
+        assert_eq!(records.keys().next(), Some("title"));
+
+        // inserting an existing key replaces the value in place
+        let len = records.len();
+        assert_eq!(
+            records.insert("title", "Rust"),
+            Some("The Rust Programming Language".to_string())
+        );
+        assert_eq!(records.len(), len);
+        assert_eq!(records[0], ("title".to_string(), "Rust".to_string()));
+
+        assert_eq!(
+            records.remove("awards_0"),
+            Some("Best Programming Book 2019".to_string())
+        );
+        assert_eq!(records.remove("awards_0"), None);
+        assert_eq!(records.get("awards_len"), Some("1"));
+        records.insert("awards_len", "0");
+        let book: Book = from_txt_records(records.clone()).unwrap();
+        assert_eq!(book.title, "Rust");
+        assert_eq!(book.awards, Some(vec![]));
+
+        // duplicate keys keep the first position and the last value
+        let records: TxtRecords = vec![
+            ("name".to_string(), "Alice".to_string()),
+            ("age".to_string(), "30".to_string()),
+            ("name".to_string(), "Bob".to_string()),
+        ]
+        .into();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records.to_string(), "name=Bob\nage=30\n");
+
+        let map: HashMap<String, String> = records.clone().into();
+        let from_map = TxtRecords::from(map);
+        let btree: std::collections::BTreeMap<String, String> = from_map.clone().into();
+        assert_eq!(
+            TxtRecords::from(btree).keys().collect::<Vec<_>>(),
+            vec!["age", "name"]
+        );
+        let person: TestStruct = from_txt_records(from_map).unwrap();
+        assert_eq!(person.name, "Bob");
+
+        let mut extended: TxtRecords = records.iter().cloned().collect();
+        extended.extend([("extra", "1")]);
+        assert_eq!(
+            extended.into_iter().last(),
+            Some(("extra".to_string(), "1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_txt_records_typed_getters() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Db {
+            host: String,
+            port: u16,
+        }
+
+        let mut services = HashMap::new();
+        services.insert(
+            "db".to_string(),
+            Db {
+                host: "localhost".to_string(),
+                port: 5432,
+            },
+        );
+        let mut records = TxtRecords::new();
+        records.extend(
+            to_txt_records(&services)
+                .unwrap()
+                .into_iter()
+                .map(|(key, value)| (format!("services.{}", key), value)),
+        );
+        records.extend(to_txt_records(&sample_matrix()).unwrap());
+
+        assert_eq!(records.get_as::<u16>("services.db.port").unwrap(), 5432);
+        assert_eq!(records.get_as::<Db>("services.db").unwrap(), services["db"]);
+        assert_eq!(
+            records.get_as::<Vec<Vec<i32>>>("data").unwrap(),
+            sample_matrix().data
+        );
+        assert_eq!(records.get_as::<Vec<i32>>("data_1").unwrap(), vec![0, 1, 0]);
+        assert!(matches!(
+            records.get_as::<u16>("services.db.host"),
+            Err(DeserializeError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            records.get_as::<u16>("services.cache.port"),
+            Err(DeserializeError::MissingField(_))
+        ));
+        let matrix: Matrix = records.get_as("").unwrap();
+        assert_eq!(matrix, sample_matrix());
+
+        // keys follow the prefix, key case and codec of the configuration
+        let config = TxtRecordConfig::builder()
+            .prefix("svc")
+            .key_case(KeyCase::Upper)
+            .build()
+            .unwrap();
+        let records = to_txt_records_with_config(&services, config.clone()).unwrap();
+        for key in ["db", "svc.db", "SVC.DB"] {
+            let db: Db = records.get_as_with_config(key, config.clone()).unwrap();
+            assert_eq!(db, services["db"]);
+        }
+        let config = TxtRecordConfig::builder()
+            .key_codec(SlashCodec)
+            .build()
+            .unwrap();
+        let records = to_txt_records_with_config(&sample_matrix(), config.clone()).unwrap();
+        let row: Vec<i32> = records.get_as_with_config("data#1", config).unwrap();
+        assert_eq!(row, vec![0, 1, 0]);
+
+        // names with digits are not array elements without the length of an array
+        let records = to_records(&[("page_2", "next")]);
+        assert_eq!(records.get_as::<String>("page_2").unwrap(), "next");
+    }
+
+    #[test]
+    fn test_length_unit_bytes_and_chars() {
+        let mut map = HashMap::new();
+        map.insert("city", "Zürich–Nord");
+        // "city=Zürich–Nord" is 16 characters, but 19 bytes in UTF-8
+        let bytes = TxtRecordConfig {
+            record_len: 16,
+            ..Default::default()
+        };
+        match to_txt_records_with_config(&map, bytes.clone()).unwrap_err() {
+            TxtRecordError::RecordTooLong {
+                actual_len,
+                unit,
+                byte_len,
+                char_len,
+                ..
+            } => {
+                assert_eq!(actual_len, 19);
+                assert_eq!(unit, LengthUnit::Bytes);
+                assert_eq!((byte_len, char_len), (19, 16));
+            }
+            err => panic!("Expected RecordTooLong error, got {:?}", err),
+        }
+        let err = to_txt_records_with_config(&map, bytes).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("19 bytes exceeds maximum of 16 (19 bytes, 16 characters)"));
+
+        let chars = TxtRecordConfig::builder()
+            .record_len(16)
+            .length_unit(LengthUnit::Chars)
+            .build()
+            .unwrap();
+        let records = to_txt_records_with_config(&map, chars.clone()).unwrap();
+        assert_eq!(records.get("city"), Some("Zürich–Nord"));
+
+        map.insert("city", "Zürich–Nord!");
+        match to_txt_records_with_config(&map, chars).unwrap_err() {
+            TxtRecordError::RecordTooLong {
+                actual_len, unit, ..
+            } => {
+                assert_eq!(actual_len, 17);
+                assert_eq!(unit, LengthUnit::Chars);
+            }
+            err => panic!("Expected RecordTooLong error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_ascii_only_records() {
+        let config = TxtRecordConfig::builder().ascii_only(true).build().unwrap();
+        let person = TestStruct {
+            name: "Zoë".to_string(),
+            age: 30,
+        };
+        match to_txt_records_with_config(&person, config.clone()).unwrap_err() {
+            TxtRecordError::NonAscii { key, character, .. } => {
+                assert_eq!(key, "name");
+                assert_eq!(character, 'ë');
+            }
+            err => panic!("Expected NonAscii error, got {:?}", err),
+        }
+        let err = to_txt_records_with_config(&person, config.clone()).unwrap_err();
+        assert!(err.to_string().contains("'ë' (U+00EB)"));
+
+        let mut map = HashMap::new();
+        map.insert("naïve", "ok");
+        assert!(matches!(
+            to_txt_records_with_config(&map, config.clone()),
+            Err(TxtRecordError::NonAscii {
+                character: 'ï', ..
+            })
+        ));
+
+        assert!(to_txt_records_with_config(&sample_book(), config).is_ok());
+    }
+
+    /// Matches a record key against a schema pattern, where `{key}` stands for any map key
+    /// and other placeholders for array indices.
+    fn matches_pattern(pattern: &str, key: &str) -> bool {
+        let Some(start) = pattern.find('{') else {
+            return pattern == key;
+        };
+        let end = start + pattern[start..].find('}').unwrap();
+        if !key.starts_with(&pattern[..start]) {
+            return false;
+        }
+        let (placeholder, rest, key) = (&pattern[start..=end], &pattern[end + 1..], &key[start..]);
+        (1..=key.len()).any(|len| {
+            let segment = &key[..len];
+            let valid = if placeholder == "{key}" {
+                true
+            } else {
+                segment.bytes().all(|b| b.is_ascii_digit())
+            };
+            valid && matches_pattern(rest, &key[len..])
+        })
+    }
+
+    #[test]
+    fn test_describe_schema() {
+        let schema = describe::<Book>().unwrap();
+        let keys: Vec<(&str, &str, bool)> = schema
+            .keys
+            .iter()
+            .map(|key| (key.pattern.as_str(), key.rust_type.as_str(), key.optional))
+            .collect();
+        assert_eq!(keys[1], ("authors_{i}.name", "String", false));
+        assert_eq!(keys[2], ("authors_{i}.email", "String", true));
+        assert_eq!(keys[4], ("authors_len", "usize", false));
+        assert_eq!(schema.keys[4].kind, schema::KeyKind::ArrayLength);
+        assert_eq!(keys[7], ("publisher.founded", "u16", false));
+        assert_eq!(keys[keys.len() - 1], ("awards_len", "usize", true));
+
+        // every record the serializer writes matches a pattern of the right kind
+        let records = to_txt_records(&sample_book()).unwrap();
+        for (key, _) in &records {
+            assert!(
+                schema.keys.iter().any(|p| matches_pattern(&p.pattern, key)),
+                "no pattern for {}",
+                key
+            );
+        }
+
+        let schema = describe_with_config::<Matrix>(TxtRecordConfig::env()).unwrap();
+        let patterns: Vec<&str> = schema.keys.iter().map(|key| key.pattern.as_str()).collect();
+        assert_eq!(
+            patterns,
+            vec![
+                "NAME",
+                "DATA_{i}_{j}",
+                "DATA_{i}_LEN",
+                "DATA_LEN",
+                "METADATA_{i}_{j}",
+                "METADATA_{i}_LEN",
+                "METADATA_LEN",
+            ]
+        );
+        let records = to_txt_records_with_config(&sample_matrix(), TxtRecordConfig::env()).unwrap();
+        for (key, _) in &records {
+            assert!(patterns.iter().any(|pattern| matches_pattern(pattern, key)));
+        }
+    }
+
+    #[test]
+    fn test_describe_maps_enums_and_output_formats() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum Protocol {
+            Tcp,
+            Udp,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Port(u16);
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Service {
+            ports: HashMap<String, Port>,
+            protocol: Protocol,
+            pair: (String, bool),
+        }
+
+        let config = TxtRecordConfig::builder().prefix("svc").build().unwrap();
+        let schema = describe_with_config::<Service>(config.clone()).unwrap();
+        let keys: Vec<(&str, &str)> = schema
+            .keys
+            .iter()
+            .map(|key| (key.pattern.as_str(), key.rust_type.as_str()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("svc.ports.{key}", "Port (u16)"),
+                ("svc.protocol", "Protocol (Tcp | Udp)"),
+                ("svc.pair_0", "String"),
+                ("svc.pair_1", "bool"),
+                ("svc.pair_len", "usize"),
+            ]
+        );
+
+        let service = Service {
+            ports: [("http".to_string(), Port(80))].into_iter().collect(),
+            protocol: Protocol::Udp,
+            pair: ("a".to_string(), true),
+        };
+        for (key, _) in &to_txt_records_with_config(&service, config).unwrap() {
+            assert!(keys
+                .iter()
+                .any(|(pattern, _)| matches_pattern(pattern, key)));
+        }
+
+        let markdown = schema.to_markdown();
+        assert!(markdown.contains("| `svc.protocol` | `Protocol (Tcp \\| Udp)` | no | value |"));
+        let json = schema.to_json();
+        assert!(json.starts_with("[\n  {\"key\": \"svc.ports.{key}\", \"type\": \"Port (u16)\""));
+        assert!(json.contains("{\"key\": \"svc.pair_len\", \"type\": \"usize\", \"optional\": false, \"kind\": \"array length\"}"));
+
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Tree {
+            children: Vec<Tree>,
+        }
+        assert!(matches!(
+            describe::<Tree>(),
+            Err(DeserializeError::Custom(_))
+        ));
+    }
+
+    #[test]
+    fn test_describe_flatten_and_key_codec() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Endpoint {
+            hosts: Vec<String>,
+            port: u16,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Flat {
+            name: String,
+            #[serde(flatten)]
+            endpoint: Endpoint,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Deployment {
+            region: String,
+            service: Flat,
+            replicas: u8,
+        }
+
+        // the fields of a struct with flattened fields are not known, so the description
+        // stops at it
+        let schema = describe::<Deployment>().unwrap();
+        assert!(!schema.complete);
+        let keys: Vec<(&str, &str)> = schema
+            .keys
+            .iter()
+            .map(|key| (key.pattern.as_str(), key.rust_type.as_str()))
+            .collect();
+        assert_eq!(keys, vec![("region", "String"), ("service.{key}", "any")]);
+        let schema = describe::<Flat>().unwrap();
+        assert!(!schema.complete);
+        assert_eq!(schema.keys[0].pattern, "{key}");
+        let flat = Flat {
+            name: "api".to_string(),
+            endpoint: Endpoint {
+                hosts: vec!["a".to_string(), "b".to_string()],
+                port: 443,
+            },
+        };
+        for (key, _) in &to_txt_records(&flat).unwrap() {
+            assert!(matches_pattern(&schema.keys[0].pattern, key));
+        }
+        assert!(describe::<Book>().unwrap().complete);
+
+        // keys are built by the codec, with the key case outside the placeholders
+        let config = TxtRecordConfig::builder()
+            .key_codec(SlashCodec)
+            .key_case(KeyCase::Upper)
+            .build()
+            .unwrap();
+        let schema = describe_with_config::<Book>(config.clone()).unwrap();
+        let patterns: Vec<&str> = schema.keys.iter().map(|key| key.pattern.as_str()).collect();
+        assert_eq!(patterns[1], "AUTHORS#{i}/NAME");
+        assert_eq!(patterns[4], "AUTHORS#");
+        assert_eq!(patterns[5], "PUBLISHER/NAME");
+        let records = to_txt_records_with_config(&sample_book(), config).unwrap();
+        for (key, _) in &records {
+            assert!(
+                patterns.iter().any(|pattern| matches_pattern(pattern, key)),
+                "no pattern for {}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_size_report() {
+        let mut book = sample_book();
+        book.authors[1].biography = "b".repeat(300);
+        book.genres.push("g".repeat(260));
+
+        // serialization fails on the first violation, the report lists all of them
+        assert!(to_txt_records(&book).is_err());
+        let mut report = size_report(&book, &TxtRecordConfig::default()).unwrap();
+        let violations: Vec<&str> = report.violations().map(|r| r.key.as_str()).collect();
+        assert_eq!(violations, vec!["authors_1.biography", "genres_2"]);
+
+        let title = &report.records[0];
+        assert_eq!(title.key, "title");
+        assert_eq!((title.key_len, title.value_len, title.len), (5, 29, 35));
+        assert_eq!(title.headroom, 220);
+        assert_eq!(
+            report.total_len,
+            report.records.iter().map(|r| r.len).sum::<usize>()
+        );
+
+        let authors = report.subtree("authors").unwrap();
+        // five author fields and the length record
+        assert_eq!(authors.records, 6);
+        let author = report.subtree("authors_1").unwrap();
+        assert_eq!(author.records, 2);
+        assert_eq!(
+            authors.len,
+            report
+                .records
+                .iter()
+                .filter(|r| r.key.starts_with("authors_"))
+                .map(|r| r.len)
+                .sum::<usize>()
+        );
+        assert_eq!(report.subtree("publisher").unwrap().records, 3);
+        assert!(report.subtree("publication").is_none());
+
+        report.sort_by_size();
+        assert_eq!(report.records[0].key, "authors_1.biography");
+        assert_eq!(report.records[0].headroom, 255 - 320);
+        assert_eq!(report.subtrees[0].path, "authors");
+    }
+
+    #[test]
+    fn test_size_report_with_config() {
+        let mut map = HashMap::new();
+        map.insert("city".to_string(), vec!["Zürich".to_string(); 2]);
+        let config = TxtRecordConfig::builder()
+            .record_len(12)
+            .length_unit(LengthUnit::Chars)
+            .ascii_only(true)
+            .build()
+            .unwrap();
+
+        let report = size_report(&map, &config).unwrap();
+        assert_eq!(report.max_len, 12);
+        let first = &report.records[0];
+        assert_eq!(first.key, "city_0");
+        assert_eq!((first.value_len, first.len, first.headroom), (6, 13, -1));
+        assert_eq!(report.violations().count(), 2);
+        assert_eq!(report.subtree("city").unwrap().records, 3);
+
+        // other errors still fail
+        let invalid = TxtRecordConfig {
+            array_separator: String::new(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            size_report(&map, &invalid),
+            Err(TxtRecordError::Config(ConfigError::EmptyArraySeparator))
+        ));
+    }
+
+    #[test]
+    fn test_key_dictionary() {
+        let book = sample_book();
+        let dictionary = KeyDictionary::new([
+            ("publisher.name", "pn"),
+            ("metadata", "m"),
+            ("authors", "au"),
+        ]);
+        let config = TxtRecordConfig::builder()
+            .key_dictionary(dictionary.clone())
+            .strict(true)
+            .build()
+            .unwrap();
+
+        let records = to_txt_records_with_config(&book, config.clone()).unwrap();
+        assert_eq!(records.get("pn"), Some("No Starch Press"));
+        assert_eq!(records.get("publisher.location"), Some("San Francisco, CA"));
+        assert_eq!(records.get("au_1.name"), Some("Carol Nichols"));
+        assert_eq!(records.get("au_len"), Some("2"));
+        assert_eq!(records.get("m.pages"), Some("552"));
+        assert!(!records.contains_key("authors_len"));
+        let decoded: Book = from_txt_records_with_config(records, config).unwrap();
+        assert_eq!(decoded, book);
+
+        // a field that reads as an alias
+        let config = TxtRecordConfig::builder()
+            .key_dictionary(KeyDictionary::new([("title", "genres")]))
+            .build()
+            .unwrap();
+        match to_txt_records_with_config(&book, config) {
+            Err(TxtRecordError::AliasCollision { key, alias }) => {
+                assert_eq!((key.as_str(), alias.as_str()), ("genres_0", "genres"));
+            }
+            other => panic!("expected an alias collision, got {:?}", other),
+        }
+
+        let duplicate = KeyDictionary::new([("title", "t"), ("metadata", "t")]);
+        assert_eq!(
+            TxtRecordConfig::builder()
+                .key_dictionary(duplicate)
+                .build()
+                .unwrap_err(),
+            ConfigError::DuplicateAlias("t".to_string())
+        );
+        assert!(matches!(
+            TxtRecordConfig::builder()
+                .key_dictionary(KeyDictionary::new([("genres", "g_0")]))
+                .build(),
+            Err(ConfigError::InvalidAlias { .. })
+        ));
+        assert_eq!(
+            TxtRecordConfig::builder()
+                .key_dictionary(KeyDictionary {
+                    derive: true,
+                    ..dictionary
+                })
+                .build()
+                .unwrap_err(),
+            ConfigError::UnpublishedKeyDictionary
+        );
+    }
+
+    #[test]
+    fn test_derived_key_dictionary() {
+        let book = sample_book();
+        let mut config = TxtRecordConfig::dns_sd();
+        config.prefix = Some("Book".to_string());
+        config.strict = true;
+        config.key_dictionary = Some(KeyDictionary::derived("Dict"));
+
+        let plain = to_txt_records_with_config(&book, TxtRecordConfig::dns_sd()).unwrap();
+        let records = to_txt_records_with_config(&book, config.clone()).unwrap();
+        assert_eq!(records[0].0, "book.dict");
+        assert_eq!(records.len(), plain.len() + 1);
+        let longest = |records: &TxtRecords| records.keys().map(str::len).max().unwrap();
+        assert!(longest(&records) < longest(&plain));
+        assert_eq!(records.get("book.a_1.h"), Some("Carol Nichols"));
+        assert_eq!(records.get("book.a#"), Some("2"));
+        assert!(records.get("book.dict").unwrap().starts_with("a=authors,"));
+        let decoded: Book = from_txt_records_with_config(records.clone(), config.clone()).unwrap();
+        assert_eq!(decoded, book);
+
+        // the aliases cannot be guessed without the dictionary
+        let mut missing = records;
+        missing.remove("book.dict");
+        match from_txt_records_with_config::<Book>(missing, config) {
+            Err(DeserializeError::MissingKeyDictionary(record)) => assert_eq!(record, "Dict"),
+            other => panic!("expected a missing dictionary, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_overflow_truncate_and_skip() {
+        let mut map = BTreeMap::new();
+        map.insert("bio".to_string(), "é".repeat(10));
+        map.insert("name".to_string(), "Ferris".to_string());
+
+        // "bio=" and "~" leave 10 bytes, which ends in the middle of the sixth "é"
+        let config = TxtRecordConfig::builder()
+            .record_len(15)
+            .overflow(OverflowPolicy::Truncate {
+                marker: "~".to_string(),
+            })
+            .build()
+            .unwrap();
+        assert!(to_txt_records_with_config(
+            &map,
+            TxtRecordConfig {
+                overflow: OverflowPolicy::Error,
+                ..config.clone()
+            }
+        )
+        .is_err());
+        let (records, adjustments) = to_txt_records_with_adjustments(&map, config.clone()).unwrap();
+        assert_eq!(records.get("bio"), Some("ééééé~"));
+        assert_eq!(records.get("name"), Some("Ferris"));
+        assert_eq!(
+            adjustments,
+            vec![Adjustment::Truncated {
+                key: "bio".to_string(),
+                original: "é".repeat(10),
+                value: "ééééé~".to_string(),
+            }]
+        );
+
+        let config = TxtRecordConfig {
+            length_unit: LengthUnit::Chars,
+            ..config
+        };
+        let records = to_txt_records_with_config(&map, config).unwrap();
+        assert_eq!(records.get("bio"), Some("éééééééééé"));
+
+        let config = TxtRecordConfig::builder()
+            .record_len(12)
+            .overflow(OverflowPolicy::Skip)
+            .build()
+            .unwrap();
+        let (records, adjustments) = to_txt_records_with_adjustments(&map, config.clone()).unwrap();
+        assert_eq!(records, to_records(&[("name", "Ferris")]));
+        assert_eq!(adjustments.len(), 1);
+        assert_eq!(adjustments[0].key(), "bio");
+
+        // skipped elements are left out of their array, which still reads back
+        let tags = vec!["a".to_string(), "b".repeat(20), "c".to_string()];
+        let (records, adjustments) = to_txt_records_with_adjustments(&tags, config).unwrap();
+        assert_eq!(adjustments.len(), 1);
+        assert_eq!(adjustments[0].key(), "_1");
+        assert_eq!(records.get("_1"), Some("c"));
+        assert_eq!(records.get("_len"), Some("2"));
+        let result: Vec<String> = from_txt_records(records).unwrap();
+        assert_eq!(result, ["a", "c"]);
+    }
+
+    #[test]
+    fn test_overflow_callback() {
+        let book = sample_book();
+        let config = TxtRecordConfig::builder()
+            .record_len(34)
+            .overflow(OverflowPolicy::callback(|key, value| {
+                if key.ends_with("biography") {
+                    OverflowAction::Replace(format!("{} chars", value.len()))
+                } else if key == "title" {
+                    OverflowAction::Truncate {
+                        marker: "...".to_string(),
+                    }
+                } else {
+                    OverflowAction::Skip
+                }
+            }))
+            .build()
+            .unwrap();
+
+        let (records, adjustments) =
+            to_txt_records_with_adjustments(&book, config.clone()).unwrap();
+        assert_eq!(records.get("title"), Some("The Rust Programming Lang..."));
+        assert_eq!(records.get("authors_0.biography"), Some("42 chars"));
+        assert!(!records.contains_key("publisher.location"));
+        let keys: Vec<&str> = adjustments.iter().map(Adjustment::key).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "title",
+                "authors_0.biography",
+                "authors_1.biography",
+                "publisher.location",
+                "awards_0"
+            ]
+        );
+
+        // a replacement that is still too long
+        let config = TxtRecordConfig {
+            record_len: 25,
+            ..config
+        };
+        match to_txt_records_with_config(&book, config) {
+            Err(TxtRecordError::RecordTooLong { key, .. }) => {
+                assert_eq!(key, "authors_0.biography")
+            }
+            other => panic!("expected a record that is too long, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_check_lossy() {
+        struct Bytes(Vec<u8>);
+
+        impl Serialize for Bytes {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(&self.0)
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Empty {}
+
+        #[derive(Serialize)]
+        enum Shape {
+            Circle { radius: u32 },
+            Point,
+        }
+
+        #[derive(Serialize)]
+        struct Drawing {
+            shapes: Vec<Shape>,
+            thumbnail: Bytes,
+            checksum: Bytes,
+            layer: Option<Empty>,
+            hidden: Option<Option<u32>>,
+        }
+
+        let drawing = Drawing {
+            shapes: vec![Shape::Point, Shape::Circle { radius: 2 }],
+            thumbnail: Bytes(vec![0xff, 0x00]),
+            checksum: Bytes(b"abc".to_vec()),
+            layer: Some(Empty {}),
+            hidden: Some(None),
+        };
+
+        let lossy = check_lossy(&drawing, &TxtRecordConfig::default()).unwrap();
+        assert_eq!(
+            lossy,
+            vec![
+                Lossy {
+                    key: "shapes_1".to_string(),
+                    kind: LossyKind::EnumVariant("Circle"),
+                },
+                Lossy {
+                    key: "thumbnail".to_string(),
+                    kind: LossyKind::InvalidUtf8,
+                },
+                Lossy {
+                    key: "layer".to_string(),
+                    kind: LossyKind::EmptySome,
+                },
+                Lossy {
+                    key: "hidden".to_string(),
+                    kind: LossyKind::EmptySome,
+                },
+            ]
+        );
+
+        // serialization still succeeds unless lossy values are rejected
+        assert!(to_txt_records(&drawing).is_ok());
+        let config = TxtRecordConfig::builder()
+            .reject_lossy(true)
+            .build()
+            .unwrap();
+        match to_txt_records_with_config(&drawing, config.clone()) {
+            Err(TxtRecordError::Lossy(rejected)) => assert_eq!(rejected, lossy),
+            other => panic!("expected lossy values, got {:?}", other),
+        }
+        assert!(to_txt_records_with_config(&sample_book(), config).is_ok());
+    }
+
+    #[test]
+    fn test_verify_roundtrip() {
+        let config = TxtRecordConfig::dns_sd();
+        verify_roundtrip(&sample_book(), &config).unwrap();
+        verify_roundtrip(&sample_book(), &TxtRecordConfig::env()).unwrap();
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Flags {
+            name: String,
+            enabled: Option<()>,
+        }
+
+        let flags = Flags {
+            name: "beta".to_string(),
+            enabled: Some(()),
+        };
+        match verify_roundtrip(&flags, &config) {
+            Err(RoundtripError::Mismatch { actual, lossy, .. }) => {
+                assert!(actual.contains("enabled: None"));
+                assert_eq!(lossy[0].kind, LossyKind::EmptySome);
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum Source {
+            File(String),
+            Inline(u32),
+        }
+
+        let err = verify_roundtrip(&Source::Inline(3), &config).unwrap_err();
+        assert!(matches!(err, RoundtripError::Deserialize { .. }));
+        assert!(err
+            .to_string()
+            .ends_with("; the root drops the name of the enum variant Inline"));
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        #[derive(Serialize)]
+        struct Items {
+            items: Vec<u32>,
+            items_0: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Tags {
+            tags: Vec<String>,
+            tags_len: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Inner {
+            b: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Nested {
+            a: Inner,
+            #[serde(flatten)]
+            extra: BTreeMap<String, u32>,
+        }
+
+        let duplicate = |result: Result<TxtRecords, TxtRecordError>| match result {
+            Err(TxtRecordError::DuplicateKey { key, first, second }) => (key, first, second),
+            other => panic!("expected a duplicate key, got {:?}", other),
+        };
+        let owned = |key: &str, first: &str, second: &str| {
+            (key.to_string(), first.to_string(), second.to_string())
+        };
+
+        let items = Items {
+            items: vec![1],
+            items_0: 2,
+        };
+        assert_eq!(
+            duplicate(to_txt_records(&items)),
+            owned("items_0", "items[0]", "items_0")
+        );
+
+        let tags = Tags {
+            tags: vec!["a".to_string()],
+            tags_len: 5,
+        };
+        let err = to_txt_records(&tags).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Key 'tags_len' is written by both tags.len() and tags_len"
+        );
+
+        let nested = Nested {
+            a: Inner { b: 1 },
+            extra: [("a.b".to_string(), 2)].into_iter().collect(),
+        };
+        assert_eq!(
+            duplicate(to_txt_records(&nested)),
+            // flattened structs are serialized as maps, so their fields are map keys too
+            owned("a.b", "[\"a\"].b", "[\"a.b\"]")
+        );
+
+        // keys that only differ in case collide once the case is applied
+        let mut map = HashMap::new();
+        map.insert("Port".to_string(), vec![80]);
+        map.insert("port".to_string(), vec![443]);
+        let (key, first, second) =
+            duplicate(to_txt_records_with_config(&map, TxtRecordConfig::dns_sd()));
+        assert_eq!(key, "port_0");
+        let mut paths = [first, second];
+        paths.sort();
+        assert_eq!(paths, ["[\"Port\"][0]", "[\"port\"][0]"]);
+    }
+
+    #[test]
+    fn test_shape_conflicts() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Server {
+            host: String,
+            port: Option<String>,
+        }
+
+        let records = to_records(&[
+            ("host", "db"),
+            ("port", "80"),
+            ("port.tls", "443"),
+            ("port_len", "2"),
+        ]);
+        let (server, warnings): (Server, _) =
+            from_txt_records_with_warnings(records.clone(), TxtRecordConfig::default()).unwrap();
+        assert_eq!(server.port.as_deref(), Some("80"));
+        assert_eq!(
+            warnings,
+            vec![ShapeConflict {
+                key: "port".to_string(),
+                records: vec![
+                    ("port".to_string(), "80".to_string()),
+                    ("port_len".to_string(), "2".to_string()),
+                    ("port.tls".to_string(), "443".to_string()),
+                ],
+            }]
+        );
+
+        let config = TxtRecordConfig::builder()
+            .strict(true)
+            .prefix("srv")
+            .build()
+            .unwrap();
+        let prefixed: TxtRecords = records
+            .iter()
+            .map(|(key, value)| (format!("srv.{}", key), value.clone()))
+            .collect();
+        let err = from_txt_records_with_config::<Server>(prefixed, config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Conflicting records at 'srv.port': srv.port=80, srv.port_len=2, srv.port.tls=443"
+        );
+
+        // typed fields see the conflict too
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Leaf {
+            a: String,
+        }
+        let records = to_records(&[("a", "x"), ("a.b", "2")]);
+        let (leaf, warnings): (Leaf, _) =
+            from_txt_records_with_warnings(records.clone(), TxtRecordConfig::default()).unwrap();
+        assert_eq!(leaf.a, "x");
+        assert_eq!(
+            warnings,
+            vec![ShapeConflict {
+                key: "a".to_string(),
+                records: vec![
+                    ("a".to_string(), "x".to_string()),
+                    ("a.b".to_string(), "2".to_string()),
+                ],
+            }]
+        );
+        let strict = TxtRecordConfig::builder().strict(true).build().unwrap();
+        assert!(matches!(
+            from_txt_records_with_config::<Leaf>(records, strict),
+            Err(DeserializeError::ShapeConflict(_))
+        ));
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Nested {
+            a: Vec<u8>,
+        }
+        let records = to_records(&[("a_len", "1"), ("a_0", "7"), ("a.b", "2")]);
+        let (nested, warnings): (Nested, _) =
+            from_txt_records_with_warnings(records, TxtRecordConfig::default()).unwrap();
+        assert_eq!(nested.a, [7]);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_shape_conflicts_in_untyped_values() {
+        let records = to_records(&[("a", "x"), ("a_len", "1"), ("a_0", "y"), ("b.c", "1")]);
+        let (value, warnings): (TxtValue, _) =
+            from_txt_records_with_warnings(records.clone(), TxtRecordConfig::default()).unwrap();
+        assert_eq!(value.get("a").and_then(TxtValue::as_str), Some("x"));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].key, "a");
+
+        // records without conflicts have no warnings
+        let (_, warnings): (Book, _) = from_txt_records_with_warnings(
+            to_txt_records(&sample_book()).unwrap(),
+            TxtRecordConfig::default(),
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    /// `routes/api%2Fv1#0`: `/` joins members, `#` indices, and map keys are escaped
+    #[derive(Debug)]
+    struct SlashCodec;
+
+    impl KeyCodec for SlashCodec {
+        fn join(&self, base: &str, segment: Segment<'_>) -> String {
+            let segment = match segment {
+                Segment::Field(name) => name.to_string(),
+                Segment::MapKey(key) => key.replace('%', "%25").replace('/', "%2F"),
+                Segment::Index(index) => return format!("{}#{}", base, index),
+            };
+            if base.is_empty() {
+                segment
+            } else {
+                format!("{}/{}", base, segment)
+            }
+        }
+
+        fn len_key(&self, base: &str) -> String {
+            format!("{}#", base)
+        }
+
+        fn split_member<'k>(
+            &self,
+            base: &str,
+            key: &'k str,
+            _exists: &dyn Fn(&str) -> bool,
+        ) -> Option<std::borrow::Cow<'k, str>> {
+            let rest = if base.is_empty() {
+                key
+            } else {
+                key.strip_prefix(base)?.strip_prefix('/')?
+            };
+            let end = rest.find(['/', '#']).unwrap_or(rest.len());
+            let member = rest[..end].replace("%2F", "/").replace("%25", "%");
+            (end > 0).then_some(std::borrow::Cow::Owned(member))
+        }
+    }
+
+    #[test]
+    fn test_key_codec_escaping() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Gateway {
+            name: String,
+            routes: BTreeMap<String, Vec<u16>>,
+        }
+
+        let gateway = Gateway {
+            name: "edge".to_string(),
+            routes: [
+                ("api/v1".to_string(), vec![80, 443]),
+                ("100%".to_string(), vec![]),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let config = TxtRecordConfig::builder()
+            .key_codec(SlashCodec)
+            .strict(true)
+            .build()
+            .unwrap();
+
+        let records = to_txt_records_with_config(&gateway, config.clone()).unwrap();
+        assert_eq!(
+            records,
+            to_records(&[
+                ("name", "edge"),
+                ("routes/100%25#", "0"),
+                ("routes/api%2Fv1#0", "80"),
+                ("routes/api%2Fv1#1", "443"),
+                ("routes/api%2Fv1#", "2"),
+            ])
+        );
+        let decoded: Gateway = from_txt_records_with_config(records, config.clone()).unwrap();
+        assert_eq!(decoded, gateway);
+
+        // strict mode still finds records that no field reads
+        let mut records = to_txt_records_with_config(&gateway, config.clone()).unwrap();
+        records.insert("routes/api%2Fv1#2", "8080");
+        assert!(matches!(
+            from_txt_records_with_config::<Gateway>(records, config),
+            Err(DeserializeError::UnknownKeys(keys)) if keys == ["routes/api%2Fv1#2"]
+        ));
+    }
+
+    #[test]
+    fn test_key_codec_with_prefix_and_case() {
+        let config = TxtRecordConfig::builder()
+            .key_codec(SlashCodec)
+            .prefix("gw")
+            .key_case(KeyCase::Upper)
+            .build()
+            .unwrap();
+
+        let book = sample_book();
+        let records = to_txt_records_with_config(&book, config.clone()).unwrap();
+        assert_eq!(records.get("GW.AUTHORS#1/NAME"), Some("Carol Nichols"));
+        assert_eq!(records.get("GW.AUTHORS#"), Some("2"));
+        assert_eq!(records.get("GW.PUBLISHER/FOUNDED"), Some("1994"));
+        let decoded: Book = from_txt_records_with_config(records, config.clone()).unwrap();
+        assert_eq!(decoded, book);
+
+        // untyped values find their members through the codec as well
+        let value: TxtValue = from_txt_records_with_config(
+            to_txt_records_with_config(&book, config.clone()).unwrap(),
+            config,
+        )
+        .unwrap();
+        assert_eq!(
+            value
+                .get("PUBLISHER")
+                .and_then(|publisher| publisher.get("NAME"))
+                .and_then(TxtValue::as_str),
+            Some("No Starch Press")
+        );
+    }
+
+    #[test]
+    fn test_builtin_key_codecs() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Range {
+            start: u32,
+            len: u32,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Selection {
+            range: Range,
+            ranges: Vec<Range>,
+            empty: Vec<u32>,
+            hosts: BTreeMap<String, u16>,
+        }
+
+        let selection = Selection {
+            range: Range { start: 2, len: 5 },
+            ranges: vec![Range { start: 0, len: 1 }],
+            empty: Vec::new(),
+            hosts: [("example.com".to_string(), 443), ("50%".to_string(), 80)]
+                .into_iter()
+                .collect(),
+        };
+
+        // `[len]` is a length next to elements and a field otherwise, without conflicts
+        let config = TxtRecordConfig::builder()
+            .key_codec(BracketCodec)
+            .strict(true)
+            .build()
+            .unwrap();
+        let records = to_txt_records_with_config(&selection, config.clone()).unwrap();
+        assert_eq!(records.get("range[len]"), Some("5"));
+        assert_eq!(records.get("ranges[0][len]"), Some("1"));
+        assert_eq!(records.get("ranges[len]"), Some("1"));
+        assert_eq!(records.get("empty[len]"), Some("0"));
+        assert_eq!(records.get("hosts[example.com]"), Some("443"));
+        let (decoded, warnings) =
+            from_txt_records_with_warnings::<Selection>(records, config).unwrap();
+        assert_eq!(decoded, selection);
+        assert!(warnings.is_empty());
+
+        let config = TxtRecordConfig::builder()
+            .key_case(KeyCase::Upper)
+            .build()
+            .unwrap();
+        let config = TxtRecordConfig {
+            key_codec: Some(std::sync::Arc::new(ZeroPaddedCodec::new(3, &config))),
+            ..config
+        };
+        let records = to_txt_records_with_config(&sample_book(), config.clone()).unwrap();
+        assert_eq!(records.get("AUTHORS_001.NAME"), Some("Carol Nichols"));
+        assert_eq!(records.get("AUTHORS_LEN"), Some("2"));
+        let decoded: Book = from_txt_records_with_config(records, config).unwrap();
+        assert_eq!(decoded, sample_book());
+
+        let codec = EscapingCodec::new(&TxtRecordConfig::default());
+        let config = TxtRecordConfig::builder()
+            .key_codec(codec)
+            .strict(true)
+            .build()
+            .unwrap();
+        let records = to_txt_records_with_config(&selection, config.clone()).unwrap();
+        assert_eq!(records.get("hosts.example%2Ecom"), Some("443"));
+        assert_eq!(records.get("hosts.50%25"), Some("80"));
+        let decoded: Selection = from_txt_records_with_config(records, config.clone()).unwrap();
+        assert_eq!(decoded, selection);
+        let path = path!["hosts", "example.com"];
+        assert_eq!(config.format_path(&path), "hosts.example%2Ecom");
+        assert_eq!(config.parse_path("hosts.example%2Ecom"), Some(path));
+    }
+
+    #[test]
+    fn test_record_set_helpers_follow_key_codec() {
+        let config = TxtRecordConfig::builder()
+            .key_codec(BracketCodec)
+            .prefix("lib")
+            .build()
+            .unwrap();
+
+        let report = size_report(&sample_book(), &config).unwrap();
+        let authors = report.subtree("lib.authors").unwrap();
+        // two authors with three and two fields, and the length
+        assert_eq!(authors.records, 6);
+        assert_eq!(report.subtree("lib.authors[1]").unwrap().records, 2);
+        assert_eq!(report.subtree("lib").unwrap().records, report.records.len());
+        assert!(report.subtree("lib.authors[1][name]").is_none());
+
+        let defaults = to_txt_records_with_config(&sample_book(), config.clone()).unwrap();
+        let overrides = to_records(&[
+            ("lib.authors[0][name]", "Ferris"),
+            ("lib.authors[0][biography]", "Crab"),
+            ("lib.authors[len]", "1"),
+        ]);
+        let (records, _) = layered::merge_layers(
+            vec![("defaults", defaults), ("overrides", overrides)],
+            &config,
+        );
+        assert!(!records
+            .iter()
+            .any(|(key, _)| key.starts_with("lib.authors[1]")));
+        assert_eq!(records.get("lib.authors[0][email]"), None);
+        let book: Book = from_txt_records_with_config(records, config.clone()).unwrap();
+        assert_eq!(book.authors.len(), 1);
+        assert_eq!(book.genres, sample_book().genres);
+
+        // helpers that rewrite keys themselves reject a codec
+        let result = TxtRecordConfig::builder()
+            .key_codec(BracketCodec)
+            .key_dictionary(KeyDictionary::derived("_keys"))
+            .build();
+        assert!(matches!(result, Err(ConfigError::ConflictingKeyCodec(_))));
+        let query_config = query::QueryConfig {
+            records: config,
+            bracket_keys: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            query::to_query_string_with_config(&sample_book(), &query_config),
+            Err(TxtRecordError::Config(ConfigError::ConflictingKeyCodec(_)))
+        ));
+    }
+
+    #[test]
+    fn test_txt_path_round_trips_every_key() {
+        let book = sample_book();
+        let configs = [
+            TxtRecordConfig::default(),
+            TxtRecordConfig::builder()
+                .prefix("lib")
+                .key_case(KeyCase::Upper)
+                .build()
+                .unwrap(),
+            TxtRecordConfig::builder()
+                .key_codec(SlashCodec)
+                .build()
+                .unwrap(),
+        ];
+        for config in configs {
+            let records = to_txt_records_with_config(&book, config.clone()).unwrap();
+            for (key, value) in &records {
+                let len_suffix = config.key_case.apply(&config.array_len_suffix).into_owned();
+                if key.ends_with(&len_suffix) || key.ends_with('#') {
+                    assert_eq!(config.parse_path(key), None, "{}", key);
+                    continue;
+                }
+                let path = config.parse_path(key).unwrap();
+                assert_eq!(&config.format_path(&path), key);
+                assert_eq!(
+                    records.get_path_with_config(&path, &config),
+                    Some(value.as_str())
+                );
+            }
+        }
+
+        let config = TxtRecordConfig::builder()
+            .key_codec(SlashCodec)
+            .build()
+            .unwrap();
+        let path = path!["routes", "api/v1", 1];
+        assert_eq!(config.format_path(&path), "routes/api%2Fv1#1");
+        assert_eq!(config.parse_path("routes/api%2Fv1#1"), Some(path.clone()));
+        assert_eq!(path.to_string(), r#"routes["api/v1"][1]"#);
+        assert_eq!(
+            path.parent(),
+            Some(TxtPath::root().field("routes").field("api/v1"))
+        );
+        assert!(path!["routes"].contains(&path));
+    }
+
+    #[test]
+    fn test_txt_path_in_errors() {
+        let config = TxtRecordConfig::builder()
+            .prefix("app")
+            .strict(true)
+            .build()
+            .unwrap();
+        let mut records = to_txt_records_with_config(&sample_book(), config.clone()).unwrap();
+        records.insert("app.editions_0.year", "2019");
+        records.insert("app.editions_len", "1");
+
+        let err = from_txt_records_with_config::<Book>(records, config.clone()).unwrap_err();
+        assert_eq!(err.paths(&config), vec![path!["editions", 0, "year"]]);
+
+        let records = to_records(&[("app.a", "1"), ("app.a.b", "2")]);
+        let err = from_txt_records_with_config::<TxtValue>(records, config.clone()).unwrap_err();
+        assert_eq!(err.paths(&config), vec![path!["a"], path!["a", "b"]]);
+    }
+
+    #[test]
+    fn test_from_txt_records_at() {
+        let book = sample_book();
+        let config = TxtRecordConfig::builder()
+            .prefix("lib")
+            .strict(true)
+            .build()
+            .unwrap();
+        let mut records = to_txt_records_with_config(&book, config.clone()).unwrap();
+
+        let publisher: Publisher =
+            from_txt_records_at_with_config(records.clone(), &path!["publisher"], config.clone())
+                .unwrap();
+        assert_eq!(publisher, book.publisher);
+        let authors: Vec<Author> =
+            from_txt_records_at_with_config(records.clone(), &path!["authors"], config.clone())
+                .unwrap();
+        assert_eq!(authors, book.authors);
+        let name: String = from_txt_records_at_with_config(
+            records.clone(),
+            &path!["authors", 1, "name"],
+            config.clone(),
+        )
+        .unwrap();
+        assert_eq!(name, "Carol Nichols");
+
+        // strict mode only looks below the path
+        records.insert("lib.publisher.country", "US");
+        let err = from_txt_records_at_with_config::<Publisher>(
+            records.clone(),
+            &path!["publisher"],
+            config.clone(),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, DeserializeError::UnknownKeys(keys) if keys == ["lib.publisher.country"])
+        );
+        from_txt_records_at_with_config::<Metadata>(
+            records.clone(),
+            &path!["metadata"],
+            config.clone(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            from_txt_records_at_with_config::<Publisher>(records, &path!["printer"], config),
+            Err(DeserializeError::MissingField(key)) if key == "lib.printer"
+        ));
+        let genres: Vec<String> =
+            from_txt_records_at(to_txt_records(&book).unwrap(), &path!["genres"]).unwrap();
+        assert_eq!(genres, book.genres);
+    }
+
+    #[test]
+    fn test_selectors() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Service {
+            port: u16,
+            hosts: Vec<String>,
+        }
+
+        let services: BTreeMap<String, Service> = [
+            ("cache", 6379, vec!["c1"]),
+            ("database", 5432, vec!["d1", "d2"]),
+        ]
+        .into_iter()
+        .map(|(name, port, hosts)| {
+            let hosts = hosts.into_iter().map(String::from).collect();
+            (name.to_string(), Service { port, hosts })
+        })
+        .collect();
+        let records = to_txt_records(&services).unwrap();
+
+        let selector: Selector = "*.port".parse().unwrap();
+        assert_eq!(
+            records.select(&selector),
+            vec![
+                (path!["cache", "port"], "6379"),
+                (path!["database", "port"], "5432")
+            ]
+        );
+        let hosts: Vec<&str> = records
+            .select(&"*.hosts[*]".parse().unwrap())
+            .into_iter()
+            .map(|(_, host)| host)
+            .collect();
+        assert_eq!(hosts, ["c1", "d1", "d2"]);
+
+        let all: Vec<(TxtPath, Service)> = records.select_as(&"*".parse().unwrap()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].0, path!["database"]);
+        assert_eq!(all[1].1, services["database"]);
+        let ports: Vec<(TxtPath, u16)> = records
+            .select_as(&Selector::from(path!["database", "port"]))
+            .unwrap();
+        assert_eq!(ports, vec![(path!["database", "port"], 5432)]);
+
+        // without a length record, `page_2` is a field and not an element of `page`
+        let pages = to_records(&[("page_2", "next"), ("page_3", "last")]);
+        assert_eq!(
+            pages.select(&"page_2".parse().unwrap()),
+            vec![(path!["page_2"], "next")]
+        );
+        let next: Vec<(TxtPath, String)> = pages.select_as(&"page_2".parse().unwrap()).unwrap();
+        assert_eq!(next, vec![(path!["page_2"], "next".to_string())]);
+        assert!(pages.select(&"page[*]".parse().unwrap()).is_empty());
+
+        let selector: Selector = r#"routes["api/v1"][*].*"#.parse().unwrap();
+        assert_eq!(selector.to_string(), r#"routes["api/v1"][*].*"#);
+        for invalid in ["a..b", "a[", "a[x]", "a[0]b", ".a"] {
+            assert!(
+                matches!(
+                    invalid.parse::<Selector>(),
+                    Err(DeserializeError::InvalidSelector(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
 }
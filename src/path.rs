@@ -0,0 +1,320 @@
+//! Typed paths that address records without spelling out the separators of a layout.
+//!
+//! A [`TxtPath`] is a list of field names and array indices. The [`path!`](crate::path!)
+//! macro builds one from string and integer literals, and a configuration formats it into a
+//! record key with [`TxtRecordConfig::format_path`] and parses a key back with
+//! [`TxtRecordConfig::parse_path`]. Both go through the configured
+//! [`key_codec`](TxtRecordConfig::key_codec), prefix and key case.
+//!
+//! ```rust
+//! use serde_txtrecord::{path, TxtRecordConfig, TxtRecords};
+//!
+//! let records: TxtRecords = [("services.cache.port", "6379"), ("services.cache.hosts_0", "a")]
+//!     .into_iter()
+//!     .collect();
+//! assert_eq!(records.get_path(&path!["services", "cache", "port"]), Some("6379"));
+//!
+//! let config = TxtRecordConfig::builder().array_separator("#").build().unwrap();
+//! let hosts = path!["services", "cache", "hosts", 0];
+//! assert_eq!(config.format_path(&hosts), "services.cache.hosts#0");
+//! assert_eq!(config.parse_path("services.cache.hosts#0"), Some(hosts));
+//! ```
+
+use std::fmt;
+
+use crate::codec::Segment;
+use crate::TxtRecordConfig;
+
+/// A step in a [`TxtPath`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PathSegment {
+    /// A struct field or map key
+    Field(String),
+    /// An array or tuple element
+    Index(usize),
+}
+
+impl From<&str> for PathSegment {
+    fn from(name: &str) -> Self {
+        PathSegment::Field(name.to_string())
+    }
+}
+
+impl From<String> for PathSegment {
+    fn from(name: String) -> Self {
+        PathSegment::Field(name)
+    }
+}
+
+impl From<usize> for PathSegment {
+    fn from(index: usize) -> Self {
+        PathSegment::Index(index)
+    }
+}
+
+/// The location of a value, independent of the separators that lay it out in a record key
+///
+/// Paths are displayed like Rust expressions, e.g. `services.cache.hosts[0]`, with names
+/// that are not identifiers quoted as in `routes["api/v1"]`. The root path is empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TxtPath {
+    segments: Vec<PathSegment>,
+}
+
+impl TxtPath {
+    /// Returns the path of the root value
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn push(&mut self, segment: impl Into<PathSegment>) {
+        self.segments.push(segment.into());
+    }
+
+    /// Returns this path extended by a field
+    pub fn field(mut self, name: impl Into<String>) -> Self {
+        self.segments.push(PathSegment::Field(name.into()));
+        self
+    }
+
+    /// Returns this path extended by an array index
+    pub fn index(mut self, index: usize) -> Self {
+        self.segments.push(PathSegment::Index(index));
+        self
+    }
+
+    /// Returns the path of the containing object or array, or `None` at the root
+    pub fn parent(&self) -> Option<TxtPath> {
+        let (_, parent) = self.segments.split_last()?;
+        Some(TxtPath {
+            segments: parent.to_vec(),
+        })
+    }
+
+    /// Returns true if `other` is this path or below it
+    pub fn contains(&self, other: &TxtPath) -> bool {
+        other.segments.starts_with(&self.segments)
+    }
+}
+
+impl<S: Into<PathSegment>> FromIterator<S> for TxtPath {
+    fn from_iter<I: IntoIterator<Item = S>>(segments: I) -> Self {
+        Self {
+            segments: segments.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<Vec<PathSegment>> for TxtPath {
+    fn from(segments: Vec<PathSegment>) -> Self {
+        Self { segments }
+    }
+}
+
+impl fmt::Display for TxtPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (position, segment) in self.segments.iter().enumerate() {
+            match segment {
+                PathSegment::Field(name) if is_identifier(name) => {
+                    if position > 0 {
+                        write!(f, ".")?;
+                    }
+                    write!(f, "{}", name)?;
+                }
+                PathSegment::Field(name) => write!(f, "[{:?}]", name)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Build a [`TxtPath`] from field names and array indices
+///
+/// ```rust
+/// use serde_txtrecord::{path, TxtPath};
+///
+/// let port = path!["services", "cache", "ports", 0];
+/// assert_eq!(port, TxtPath::root().field("services").field("cache").field("ports").index(0));
+/// assert_eq!(port.to_string(), "services.cache.ports[0]");
+/// assert!(path![].is_root());
+/// ```
+#[macro_export]
+macro_rules! path {
+    ($($segment:expr),* $(,)?) => {
+        <$crate::TxtPath as ::core::iter::FromIterator<$crate::PathSegment>>::from_iter([
+            $($crate::PathSegment::from($segment)),*
+        ])
+    };
+}
+
+impl TxtRecordConfig {
+    /// Returns the record key of a path, with the prefix and key case applied
+    ///
+    /// Names are passed to the codec as [`Segment::MapKey`], so that they are escaped by
+    /// codecs that escape map keys.
+    pub fn format_path(&self, path: &TxtPath) -> String {
+        let key = self.path_key(path);
+        let key = match &self.prefix {
+            Some(prefix) if key.is_empty() => prefix.clone(),
+            Some(prefix) => format!("{}{}{}", prefix, self.object_separator, key),
+            None => key,
+        };
+        self.key_case.apply(&key).into_owned()
+    }
+
+    /// Returns the key of a path as the deserializer sees it, before the prefix and key case
+    /// are applied
+    pub(crate) fn path_key(&self, path: &TxtPath) -> String {
+        let codec = self.codec();
+        path.segments
+            .iter()
+            .fold(String::new(), |base, segment| match segment {
+                PathSegment::Field(name) => codec.join(&base, Segment::MapKey(name)),
+                PathSegment::Index(index) => codec.join(&base, Segment::Index(*index)),
+            })
+    }
+
+    /// Returns the path of the value a record key holds
+    ///
+    /// Returns `None` for keys outside the prefix, length records and keys that this layout
+    /// does not produce. Names are returned with the key case of the record key. Without the
+    /// other records, the default layout reads `tags_0` as an element of `tags`, even if it
+    /// was written by a field named `tags_0`.
+    pub fn parse_path(&self, key: &str) -> Option<TxtPath> {
+        self.parse_path_with(key, &|_| true)
+    }
+
+    /// Returns the path of a record key, where `exists` tells whether a key below the prefix
+    /// exists, so that names such as `page_2` are not read as array elements without the
+    /// length record of their array
+    pub(crate) fn parse_path_with(
+        &self,
+        key: &str,
+        exists: &dyn Fn(&str) -> bool,
+    ) -> Option<TxtPath> {
+        let case = self.key_case;
+        let key = match &self.prefix {
+            Some(prefix) => {
+                let prefix = case.apply(prefix);
+                let rest = key.strip_prefix(prefix.as_ref())?;
+                if rest.is_empty() {
+                    return Some(TxtPath::root());
+                }
+                rest.strip_prefix(case.apply(&self.object_separator).as_ref())?
+            }
+            None => key,
+        };
+
+        let codec = self.codec();
+        let mut path = TxtPath::root();
+        let mut base = String::new();
+        while base != key {
+            let next = if let Some((index, next)) = self.split_index(&base, key) {
+                path.segments.push(PathSegment::Index(index));
+                next
+            } else {
+                let name = codec.split_member(&base, key, exists)?;
+                let next = case
+                    .apply(&codec.join(&base, Segment::MapKey(&name)))
+                    .into_owned();
+                path.segments.push(PathSegment::Field(name.into_owned()));
+                next
+            };
+            if next.len() <= base.len() || !key.starts_with(next.as_str()) {
+                return None;
+            }
+            base = next;
+        }
+        Some(path)
+    }
+
+    /// Returns the index of the array element at `base` that a key belongs to, and the key of
+    /// that element
+    ///
+    /// The codec only joins indices, so the start of an index is found by comparing the keys
+    /// of two elements, which also works for zero-padded indices.
+    pub(crate) fn split_index(&self, base: &str, key: &str) -> Option<(usize, String)> {
+        let codec = self.codec();
+        let case = self.key_case;
+        let first = case
+            .apply(&codec.join(base, Segment::Index(1)))
+            .into_owned();
+        let second = case
+            .apply(&codec.join(base, Segment::Index(2)))
+            .into_owned();
+        let common = first
+            .char_indices()
+            .zip(second.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(first.len(), |((pos, _), _)| pos);
+        let start = first[..common].trim_end_matches(|c: char| c.is_ascii_digit());
+
+        let rest = key.strip_prefix(start)?;
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let index: usize = rest[..digits].parse().ok()?;
+        let element = case
+            .apply(&codec.join(base, Segment::Index(index)))
+            .into_owned();
+        key.starts_with(element.as_str())
+            .then_some((index, element))
+    }
+
+    /// Returns what record keys start with below the prefix, e.g. `app.`, with the key case
+    /// applied
+    pub(crate) fn namespace(&self) -> String {
+        let namespace = match &self.prefix {
+            Some(prefix) => format!("{}{}", prefix, self.object_separator),
+            None => String::new(),
+        };
+        self.key_case.apply(&namespace).into_owned()
+    }
+
+    /// Returns the keys of the objects and arrays that contain a record key below the prefix,
+    /// outermost first, where `exists` tells whether a key below the prefix exists
+    ///
+    /// A length record is contained by its array, e.g. `authors_len` by `authors`.
+    pub(crate) fn parent_keys(&self, key: &str, exists: &dyn Fn(&str) -> bool) -> Vec<String> {
+        let codec = self.codec();
+        let mut parents = Vec::new();
+        let mut base = String::new();
+        while base != key {
+            let next = if let Some((_, next)) = self.split_index(&base, key) {
+                next
+            } else if let Some(name) = codec.split_member(&base, key, exists) {
+                self.key_case
+                    .apply(&codec.join(&base, Segment::MapKey(&name)))
+                    .into_owned()
+            } else {
+                break;
+            };
+            if next.len() <= base.len() || !key.starts_with(next.as_str()) {
+                break;
+            }
+            if !base.is_empty() {
+                parents.push(std::mem::replace(&mut base, next));
+            } else {
+                base = next;
+            }
+        }
+        if !base.is_empty() && base != key {
+            parents.push(base);
+        }
+        parents
+    }
+}
@@ -0,0 +1,194 @@
+//! URL query strings and `application/x-www-form-urlencoded` bodies.
+//!
+//! Records are joined with `&` and keys and values are percent-encoded as described in
+//! RFC 3986, so that only unreserved characters (`A-Z a-z 0-9 - . _ ~`) are written as-is.
+//! With [`QueryConfig::form_encoding`], spaces are written as `+` as HTML forms do.
+//! When reading, `+` is decoded as a space in form encoding only.
+//!
+//! Keys use the separators of the record configuration, or the bracket style of
+//! [`BracketCodec`] (`items[0]`, `a[b]`) with [`QueryConfig::bracket_keys`].
+//!
+//! ```rust
+//! use serde::{Serialize, Deserialize};
+//! use serde_txtrecord::query::QueryConfig;
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Search {
+//!     q: String,
+//!     tags: Vec<String>,
+//! }
+//!
+//! let search = Search {
+//!     q: "rust & serde".to_string(),
+//!     tags: vec!["dns".to_string()],
+//! };
+//!
+//! let query = serde_txtrecord::to_query_string(&search).unwrap();
+//! assert_eq!(query, "q=rust%20%26%20serde&tags_0=dns&tags_len=1");
+//!
+//! let config = QueryConfig { bracket_keys: true, form_encoding: true, ..Default::default() };
+//! let query = serde_txtrecord::query::to_query_string_with_config(&search, &config).unwrap();
+//! assert_eq!(query, "q=rust+%26+serde&tags%5B0%5D=dns&tags%5Blen%5D=1");
+//!
+//! let parsed: Search = serde_txtrecord::query::from_query_string_with_config(&query, &config).unwrap();
+//! assert_eq!(search, parsed);
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    from_txt_records_with_config, to_txt_records_with_config, BracketCodec, ConfigError,
+    DeserializeError, TxtRecordConfig, TxtRecordError,
+};
+
+/// Configuration for query strings
+#[derive(Debug, Clone)]
+pub struct QueryConfig {
+    /// Configuration of the underlying records (default: [`TxtRecordConfig::query_string`])
+    pub records: TxtRecordConfig,
+    /// Write spaces as `+` and read `+` as a space, as in HTML forms (default: false)
+    pub form_encoding: bool,
+    /// Write keys in bracket style with [`BracketCodec`], e.g. `authors[0][name]` instead of
+    /// `authors_0.name`, and read them back (default: false)
+    pub bracket_keys: bool,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self {
+            records: TxtRecordConfig::query_string(),
+            form_encoding: false,
+            bracket_keys: false,
+        }
+    }
+}
+
+impl QueryConfig {
+    /// Check that the configuration can be written and read back
+    ///
+    /// Bracket keys are laid out by [`BracketCodec`], so they cannot be combined with
+    /// another [`key_codec`](TxtRecordConfig::key_codec).
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.bracket_keys && self.records.key_codec.is_some() {
+            return Err(ConfigError::ConflictingKeyCodec("bracket keys".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns the configuration of the records, with the bracket codec if enabled
+    fn record_config(&self) -> TxtRecordConfig {
+        let mut records = self.records.clone();
+        if self.bracket_keys {
+            records.key_codec = Some(Arc::new(BracketCodec));
+        }
+        records
+    }
+}
+
+/// Serialize a value to a URL query string
+pub fn to_query_string<T>(value: &T) -> Result<String, TxtRecordError>
+where
+    T: Serialize,
+{
+    to_query_string_with_config(value, &QueryConfig::default())
+}
+
+/// Serialize a value to a URL query string with custom configuration
+pub fn to_query_string_with_config<T>(
+    value: &T,
+    config: &QueryConfig,
+) -> Result<String, TxtRecordError>
+where
+    T: Serialize,
+{
+    config.validate()?;
+    let records = to_txt_records_with_config(value, config.record_config())?;
+    let pairs: Vec<String> = records
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                encode(key, config.form_encoding),
+                encode(value, config.form_encoding)
+            )
+        })
+        .collect();
+    Ok(pairs.join("&"))
+}
+
+/// Deserialize a value from a URL query string
+///
+/// A leading `?` is ignored.
+pub fn from_query_string<T>(input: &str) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_query_string_with_config(input, &QueryConfig::default())
+}
+
+/// Deserialize a value from a URL query string with custom configuration
+pub fn from_query_string_with_config<T>(
+    input: &str,
+    config: &QueryConfig,
+) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    config.validate()?;
+    let input = input.strip_prefix('?').unwrap_or(input);
+
+    let mut records = Vec::new();
+    for pair in input.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        records.push((
+            decode(key, config.form_encoding)?,
+            decode(value, config.form_encoding)?,
+        ));
+    }
+    from_txt_records_with_config(records, config.record_config())
+}
+
+/// Percent-encode everything but the RFC 3986 unreserved characters
+fn encode(input: &str, form_encoding: bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b' ' if form_encoding => encoded.push('+'),
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn decode(input: &str, form_encoding: bool) -> Result<String, DeserializeError> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'%' => {
+                let hex = [iter.next(), iter.next()];
+                let decoded = match hex {
+                    [Some(hi), Some(lo)] => std::str::from_utf8(&[hi, lo])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                    _ => None,
+                };
+                bytes.push(decoded.ok_or_else(|| {
+                    DeserializeError::InvalidFormat(format!(
+                        "invalid percent-encoding in '{}'",
+                        input
+                    ))
+                })?);
+            }
+            b'+' if form_encoding => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| {
+        DeserializeError::InvalidFormat(format!("'{}' does not decode to UTF-8", input))
+    })
+}
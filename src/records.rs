@@ -0,0 +1,367 @@
+//! An ordered collection of TXT records with unique keys.
+
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Deref;
+
+use crate::de::resolved_deserializer;
+use crate::{
+    from_txt_records_at_with_config, from_txt_records_with_config, text, DeserializeError,
+    Selector, TxtPath, TxtRecordConfig,
+};
+
+/// TXT records in insertion order, with unique keys and constant time lookups
+///
+/// Inserting a key that already exists replaces its value and keeps its position. The records
+/// can be used as a slice of `(key, value)` pairs, and are displayed in the line-oriented
+/// [`text`] format.
+///
+/// ```rust
+/// use serde_txtrecord::TxtRecords;
+///
+/// let records: TxtRecords = [("services.db.port", "5432"), ("services.db.host", "db")]
+///     .into_iter()
+///     .collect();
+///
+/// assert_eq!(records.get("services.db.host"), Some("db"));
+/// assert_eq!(records.get_as::<u16>("services.db.port").unwrap(), 5432);
+/// assert_eq!(records.to_string(), "services.db.port=5432\nservices.db.host=db\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TxtRecords {
+    records: Vec<(String, String)>,
+    index: HashMap<String, usize>,
+}
+
+impl TxtRecords {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            records: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the value of a record
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.index
+            .get(key)
+            .map(|&position| self.records[position].1.as_str())
+    }
+
+    /// Returns the value of the record at a path, in the default layout
+    pub fn get_path(&self, path: &TxtPath) -> Option<&str> {
+        self.get(&TxtRecordConfig::default().format_path(path))
+    }
+
+    /// Returns the value of the record at a path, in the layout of a configuration
+    pub fn get_path_with_config(&self, path: &TxtPath, config: &TxtRecordConfig) -> Option<&str> {
+        self.get(&config.format_path(path))
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Insert a record, returning the previous value if the key already existed
+    ///
+    /// An existing record keeps its position.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        let key = key.into();
+        let value = value.into();
+        match self.index.get(&key) {
+            Some(&position) => Some(std::mem::replace(&mut self.records[position].1, value)),
+            None => {
+                self.index.insert(key.clone(), self.records.len());
+                self.records.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Remove a record, returning its value
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let position = self.index.remove(key)?;
+        let (_, value) = self.records.remove(position);
+        for other in self.index.values_mut() {
+            if *other > position {
+                *other -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    /// Keep only the records for which `keep` returns true
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&str, &str) -> bool,
+    {
+        let len = self.records.len();
+        self.records.retain(|(key, value)| keep(key, value));
+        if self.records.len() != len {
+            self.reindex();
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.records.iter().map(|(key, _)| key.as_str())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &str> {
+        self.records.iter().map(|(_, value)| value.as_str())
+    }
+
+    /// Sort the records by key, then by value
+    pub fn sort(&mut self) {
+        self.records.sort();
+        self.reindex();
+    }
+
+    /// Sort the records with a comparison of `(key, value)` pairs
+    pub fn sort_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&(String, String), &(String, String)) -> std::cmp::Ordering,
+    {
+        self.records.sort_by(compare);
+        self.reindex();
+    }
+
+    pub fn into_vec(self) -> Vec<(String, String)> {
+        self.records
+    }
+
+    /// Deserialize the value at `key`, which may be a single record, an array or an object
+    ///
+    /// With an empty key, the whole set of records is deserialized.
+    /// [`get_as_with_config`](Self::get_as_with_config) accepts keys with or without the
+    /// prefix.
+    pub fn get_as<T>(&self, key: &str) -> Result<T, DeserializeError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.get_as_with_config(key, TxtRecordConfig::default())
+    }
+
+    /// Deserialize the value at `key` with custom configuration
+    pub fn get_as_with_config<T>(
+        &self,
+        key: &str,
+        config: TxtRecordConfig,
+    ) -> Result<T, DeserializeError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        if key.is_empty() {
+            return from_txt_records_with_config(self.clone(), config);
+        }
+
+        // the key may leave out the prefix
+        let key = config.key_case.apply(key);
+        let path = self.parse_key(&key, &config).or_else(|| {
+            let prefix = config.prefix.as_ref()?;
+            let full_key = format!("{}{}{}", prefix, config.object_separator, key);
+            self.parse_key(&config.key_case.apply(&full_key), &config)
+        });
+        match path {
+            Some(path) => from_txt_records_at_with_config(self.clone(), &path, config),
+            None => Err(DeserializeError::MissingField(key.into_owned())),
+        }
+    }
+
+    /// Returns the path of a record key, reading `tags_0` as an element of `tags` only if the
+    /// records have a length for `tags`, as the deserializer does
+    fn parse_key(&self, key: &str, config: &TxtRecordConfig) -> Option<TxtPath> {
+        let namespace = config.namespace();
+        let exists = |key: &str| self.contains_key(&format!("{}{}", namespace, key));
+        config.parse_path_with(key, &exists)
+    }
+
+    /// Returns the records whose path matches a selector, in the default layout
+    pub fn select(&self, selector: &Selector) -> Vec<(TxtPath, &str)> {
+        self.select_with_config(selector, &TxtRecordConfig::default())
+    }
+
+    /// Returns the records whose path matches a selector, in record order
+    ///
+    /// Only records that hold a value are matched, not the length records of arrays.
+    pub fn select_with_config(
+        &self,
+        selector: &Selector,
+        config: &TxtRecordConfig,
+    ) -> Vec<(TxtPath, &str)> {
+        self.records
+            .iter()
+            .filter_map(|(key, value)| {
+                let path = self.parse_key(key, config)?;
+                selector.matches(&path).then_some((path, value.as_str()))
+            })
+            .collect()
+    }
+
+    /// Deserialize every value whose path matches a selector, in the default layout
+    pub fn select_as<T>(&self, selector: &Selector) -> Result<Vec<(TxtPath, T)>, DeserializeError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.select_as_with_config(selector, TxtRecordConfig::default())
+    }
+
+    /// Deserialize every value whose path matches a selector, ordered by path
+    ///
+    /// A selector may match single records, arrays or objects. Values are found through the
+    /// records below them, so arrays without elements are not matched.
+    pub fn select_as_with_config<T>(
+        &self,
+        selector: &Selector,
+        config: TxtRecordConfig,
+    ) -> Result<Vec<(TxtPath, T)>, DeserializeError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let paths: BTreeSet<TxtPath> = self
+            .records
+            .iter()
+            .filter_map(|(key, _)| selector.matched_prefix(&self.parse_key(key, &config)?))
+            .collect();
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut deserializer = resolved_deserializer(self.clone(), config)?;
+        paths
+            .into_iter()
+            .map(|path| {
+                let value = deserializer.deserialize_at(&path)?;
+                Ok((path, value))
+            })
+            .collect()
+    }
+
+    fn reindex(&mut self) {
+        self.index = self
+            .records
+            .iter()
+            .enumerate()
+            .map(|(position, (key, _))| (key.clone(), position))
+            .collect();
+    }
+}
+
+impl Deref for TxtRecords {
+    type Target = [(String, String)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.records
+    }
+}
+
+impl PartialEq for TxtRecords {
+    fn eq(&self, other: &Self) -> bool {
+        self.records == other.records
+    }
+}
+
+impl Eq for TxtRecords {}
+
+impl PartialEq<Vec<(String, String)>> for TxtRecords {
+    fn eq(&self, other: &Vec<(String, String)>) -> bool {
+        self.records == *other
+    }
+}
+
+impl PartialEq<TxtRecords> for Vec<(String, String)> {
+    fn eq(&self, other: &TxtRecords) -> bool {
+        *self == other.records
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for TxtRecords
+where
+    K: Into<String>,
+    V: Into<String>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut records = TxtRecords::new();
+        records.extend(iter);
+        records
+    }
+}
+
+impl<K, V> Extend<(K, V)> for TxtRecords
+where
+    K: Into<String>,
+    V: Into<String>,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl IntoIterator for TxtRecords {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TxtRecords {
+    type Item = &'a (String, String);
+    type IntoIter = std::slice::Iter<'a, (String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.iter()
+    }
+}
+
+impl From<Vec<(String, String)>> for TxtRecords {
+    /// Later records replace earlier records with the same key
+    fn from(records: Vec<(String, String)>) -> Self {
+        records.into_iter().collect()
+    }
+}
+
+impl From<TxtRecords> for Vec<(String, String)> {
+    fn from(records: TxtRecords) -> Self {
+        records.records
+    }
+}
+
+impl From<HashMap<String, String>> for TxtRecords {
+    fn from(records: HashMap<String, String>) -> Self {
+        records.into_iter().collect()
+    }
+}
+
+impl From<BTreeMap<String, String>> for TxtRecords {
+    fn from(records: BTreeMap<String, String>) -> Self {
+        records.into_iter().collect()
+    }
+}
+
+impl From<TxtRecords> for HashMap<String, String> {
+    fn from(records: TxtRecords) -> Self {
+        records.records.into_iter().collect()
+    }
+}
+
+impl From<TxtRecords> for BTreeMap<String, String> {
+    fn from(records: TxtRecords) -> Self {
+        records.records.into_iter().collect()
+    }
+}
+
+impl fmt::Display for TxtRecords {
+    /// Writes the records in the line-oriented [`text`] format
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut output = Vec::new();
+        text::write_records(&mut output, &self.records, "=").map_err(|_| fmt::Error)?;
+        f.write_str(&String::from_utf8_lossy(&output))
+    }
+}
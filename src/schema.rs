@@ -0,0 +1,752 @@
+//! Key schemas that list the records a type is written to.
+//!
+//! [`describe`] traces the `Deserialize` implementation of a type with sample values, in the
+//! style of serde-reflection, and lists each key pattern with its Rust type. Array elements
+//! are written with placeholders such as `authors_{i}`, and map entries as `ports.{key}`.
+//!
+//! ```rust
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Author {
+//!     name: String,
+//!     email: Option<String>,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Book {
+//!     title: String,
+//!     authors: Vec<Author>,
+//! }
+//!
+//! let schema = serde_txtrecord::describe::<Book>().unwrap();
+//! assert_eq!(
+//!     schema.to_markdown(),
+//!     "\
+//! | Key | Type | Optional | Record |
+//! | --- | --- | --- | --- |
+//! | `title` | `String` | no | value |
+//! | `authors_{i}.name` | `String` | no | value |
+//! | `authors_{i}.email` | `String` | yes | value |
+//! | `authors_len` | `usize` | no | array length |
+//! "
+//! );
+//! ```
+//!
+//! Types that contain themselves cannot be described, and enums with data are described by
+//! their first variant.
+//!
+//! serde reads a struct with `#[serde(flatten)]` fields as a map, whose fields are only known to
+//! its `Deserialize` implementation. Its records are listed as `{key}` of type `any`, and the
+//! description stops there and is marked [incomplete](KeySchema::complete).
+
+use serde::de::{self, IntoDeserializer};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer};
+use std::fmt;
+
+use crate::codec::Segment;
+use crate::{DeserializeError, TxtRecordConfig};
+
+/// The key patterns of a type, in the order of its fields
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeySchema {
+    pub keys: Vec<KeyPattern>,
+    /// False if the type contains a struct with `#[serde(flatten)]` fields, after which the
+    /// keys are not listed
+    pub complete: bool,
+}
+
+/// A key pattern with the Rust type of its value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPattern {
+    /// Key with `{i}`, `{j}`, ... for array indices and `{key}` for map keys
+    pub pattern: String,
+    /// Rust type of the value, e.g. `u16` or `Genre (Fiction | Poetry)` for unit enums
+    pub rust_type: String,
+    /// Whether the record can be missing, because it is inside an `Option`
+    pub optional: bool,
+    pub kind: KeyKind,
+}
+
+/// The kind of record a key pattern stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    /// A leaf value
+    Value,
+    /// The length of an array
+    ArrayLength,
+}
+
+impl fmt::Display for KeyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyKind::Value => write!(f, "value"),
+            KeyKind::ArrayLength => write!(f, "array length"),
+        }
+    }
+}
+
+impl KeySchema {
+    /// Render the schema as a Markdown table
+    pub fn to_markdown(&self) -> String {
+        let mut output =
+            String::from("| Key | Type | Optional | Record |\n| --- | --- | --- | --- |\n");
+        for key in &self.keys {
+            output.push_str(&format!(
+                "| `{}` | `{}` | {} | {} |\n",
+                key.pattern.replace('|', "\\|"),
+                key.rust_type.replace('|', "\\|"),
+                if key.optional { "yes" } else { "no" },
+                key.kind
+            ));
+        }
+        output
+    }
+
+    /// Render the schema as a JSON array, with one object per key pattern
+    pub fn to_json(&self) -> String {
+        let keys: Vec<String> = self
+            .keys
+            .iter()
+            .map(|key| {
+                format!(
+                    "  {{\"key\": {}, \"type\": {}, \"optional\": {}, \"kind\": {}}}",
+                    json_string(&key.pattern),
+                    json_string(&key.rust_type),
+                    key.optional,
+                    json_string(&key.kind.to_string())
+                )
+            })
+            .collect();
+        if keys.is_empty() {
+            return "[]".to_string();
+        }
+        format!("[\n{}\n]", keys.join(",\n"))
+    }
+}
+
+impl fmt::Display for KeySchema {
+    /// Writes the schema as a Markdown table
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_markdown())
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Describe the records that `to_txt_records` writes for a type
+pub fn describe<T>() -> Result<KeySchema, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    describe_with_config::<T>(TxtRecordConfig::default())
+}
+
+/// Describe the records that `to_txt_records_with_config` writes for a type with custom
+/// configuration
+///
+/// Keys are built with the [`key_codec`](TxtRecordConfig::key_codec) of the configuration,
+/// and its key case and prefix are applied to the patterns.
+pub fn describe_with_config<T>(config: TxtRecordConfig) -> Result<KeySchema, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    config.validate()?;
+    let mut tracer = Tracer {
+        config,
+        keys: Vec::new(),
+        current_key: String::new(),
+        optional: false,
+        depth: 0,
+        structs: Vec::new(),
+        newtype: None,
+        flattened: false,
+    };
+    match T::deserialize(&mut tracer) {
+        Ok(_) => Ok(KeySchema {
+            keys: tracer.keys,
+            complete: true,
+        }),
+        // the struct with flattened fields could not be given its fields
+        Err(_) if tracer.flattened => Ok(KeySchema {
+            keys: tracer.keys,
+            complete: false,
+        }),
+        Err(err) => Err(err),
+    }
+}
+
+/// A deserializer that records the key of every value it is asked for, and answers with
+/// sample values.
+struct Tracer {
+    config: TxtRecordConfig,
+    keys: Vec<KeyPattern>,
+    current_key: String,
+    // whether the current key is inside an `Option`
+    optional: bool,
+    // number of arrays around the current key, to name their index placeholders
+    depth: usize,
+    // structs around the current key, to detect recursive types
+    structs: Vec<&'static str>,
+    // name of the newtype struct around the next leaf
+    newtype: Option<&'static str>,
+    // a struct with flattened fields was read as a map
+    flattened: bool,
+}
+
+impl Tracer {
+    fn push(&mut self, key: String, rust_type: String, kind: KeyKind) {
+        let key = match &self.config.prefix {
+            Some(prefix) if key.is_empty() => prefix.clone(),
+            Some(prefix) => format!("{}{}{}", prefix, self.config.object_separator, key),
+            None => key,
+        };
+        // placeholders keep their case
+        let case = self.config.key_case;
+        let mut pattern = String::new();
+        let mut rest = key.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .map_or(rest.len(), |end| start + end + 1);
+            pattern.push_str(&case.apply(&rest[..start]));
+            pattern.push_str(&rest[start..end]);
+            rest = &rest[end..];
+        }
+        pattern.push_str(&case.apply(rest));
+        self.keys.push(KeyPattern {
+            pattern,
+            rust_type,
+            optional: self.optional,
+            kind,
+        });
+    }
+
+    fn leaf(&mut self, rust_type: &str) {
+        let rust_type = match self.newtype.take() {
+            Some(name) => format!("{} ({})", name, rust_type),
+            None => rust_type.to_string(),
+        };
+        self.push(self.current_key.clone(), rust_type, KeyKind::Value);
+    }
+
+    fn array_length(&mut self) {
+        let key = self.config.codec().len_key(&self.current_key);
+        self.push(key, "usize".to_string(), KeyKind::ArrayLength);
+    }
+
+    fn child_key(&self, segment: Segment) -> String {
+        self.config.codec().join(&self.current_key, segment)
+    }
+
+    /// Returns the key of an array element with a placeholder for its index
+    ///
+    /// The codec only joins indices, so the index is found by comparing the keys of two
+    /// elements, which also works for zero-padded indices.
+    fn element_key(&self, placeholder: &str) -> String {
+        let codec = self.config.codec();
+        let first = codec.join(&self.current_key, Segment::Index(1));
+        let second = codec.join(&self.current_key, Segment::Index(2));
+        let base = if first.starts_with(self.current_key.as_str()) {
+            self.current_key.len()
+        } else {
+            0
+        };
+        let mut start = first
+            .char_indices()
+            .zip(second.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(first.len(), |((pos, _), _)| pos);
+        while start > base && first[..start].ends_with(|c: char| c.is_ascii_digit()) {
+            start -= 1;
+        }
+        let end = first[start..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(first.len(), |end| start + end);
+        format!("{}{}{}", &first[..start], placeholder, &first[end..])
+    }
+
+    fn placeholder(&self) -> String {
+        const NAMES: [&str; 4] = ["i", "j", "k", "l"];
+        match NAMES.get(self.depth) {
+            Some(name) => format!("{{{}}}", name),
+            None => format!("{{i{}}}", self.depth),
+        }
+    }
+}
+
+macro_rules! trace_leaf {
+    ($($method:ident => $visit:ident($sample:expr) as $name:expr,)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: de::Visitor<'de>,
+            {
+                self.leaf($name);
+                visitor.$visit($sample)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for &mut Tracer {
+    type Error = DeserializeError;
+
+    trace_leaf! {
+        deserialize_bool => visit_bool(false) as "bool",
+        deserialize_i8 => visit_i8(0) as "i8",
+        deserialize_i16 => visit_i16(0) as "i16",
+        deserialize_i32 => visit_i32(0) as "i32",
+        deserialize_i64 => visit_i64(0) as "i64",
+        deserialize_i128 => visit_i128(0) as "i128",
+        deserialize_u8 => visit_u8(0) as "u8",
+        deserialize_u16 => visit_u16(0) as "u16",
+        deserialize_u32 => visit_u32(0) as "u32",
+        deserialize_u64 => visit_u64(0) as "u64",
+        deserialize_u128 => visit_u128(0) as "u128",
+        deserialize_f32 => visit_f32(0.0) as "f32",
+        deserialize_f64 => visit_f64(0.0) as "f64",
+        deserialize_char => visit_char('a') as "char",
+        deserialize_str => visit_str("") as "String",
+        deserialize_string => visit_str("") as "String",
+        deserialize_bytes => visit_bytes(&[]) as "bytes",
+        deserialize_byte_buf => visit_bytes(&[]) as "bytes",
+        deserialize_identifier => visit_str("") as "String",
+    }
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.leaf("any");
+        visitor.visit_unit()
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let optional = std::mem::replace(&mut self.optional, true);
+        let value = visitor.visit_some(&mut *self);
+        self.optional = optional;
+        value
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        // unit values are not written
+        self.newtype = None;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.newtype = Some(name);
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.newtype = None;
+        let element = self.element_key(&self.placeholder());
+        // the length follows the elements, as in the serializer
+        let value = visitor.visit_seq(TraceSeq::new(self, vec![element]))?;
+        self.array_length();
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.newtype = None;
+        let elements = (0..len)
+            .map(|index| self.child_key(Segment::Index(index)))
+            .collect();
+        let value = visitor.visit_seq(TraceSeq::new(self, elements))?;
+        self.array_length();
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.newtype = None;
+        let entry = self.child_key(Segment::MapKey("{key}"));
+        visitor.visit_map(TraceMap::new(self, vec![(None, entry)]))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if self.structs.contains(&name) {
+            return Err(DeserializeError::Custom(format!(
+                "cannot describe the recursive type {}",
+                name
+            )));
+        }
+
+        self.newtype = None;
+        let entries = fields
+            .iter()
+            .map(|field| {
+                let key = self.child_key(Segment::Field(field));
+                (Some(*field), key)
+            })
+            .collect();
+        self.structs.push(name);
+        let value = visitor.visit_map(TraceMap::new(self, entries));
+        self.structs.pop();
+        value
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if variants.is_empty() {
+            return Err(DeserializeError::Custom(format!(
+                "cannot describe the enum {} without variants",
+                name
+            )));
+        }
+        visitor.visit_enum(TraceEnum {
+            tracer: self,
+            name,
+            variants,
+        })
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+/// Visits the given element keys once each.
+struct TraceSeq<'a> {
+    tracer: &'a mut Tracer,
+    elements: std::vec::IntoIter<String>,
+}
+
+impl<'a> TraceSeq<'a> {
+    fn new(tracer: &'a mut Tracer, elements: Vec<String>) -> Self {
+        Self {
+            tracer,
+            elements: elements.into_iter(),
+        }
+    }
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for TraceSeq<'a> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        let Some(key) = self.elements.next() else {
+            return Ok(None);
+        };
+
+        let parent = std::mem::replace(&mut self.tracer.current_key, key);
+        self.tracer.depth += 1;
+        let value = seed.deserialize(&mut *self.tracer);
+        self.tracer.depth -= 1;
+        self.tracer.current_key = parent;
+        value.map(Some)
+    }
+}
+
+/// Visits the given entries once each, with the struct field name or a sample map key.
+struct TraceMap<'a> {
+    tracer: &'a mut Tracer,
+    entries: std::vec::IntoIter<(Option<&'static str>, String)>,
+    next_key: Option<String>,
+    // the sample map key was read as a field name, by a struct with flattened fields
+    identifier: bool,
+}
+
+impl<'a> TraceMap<'a> {
+    fn new(tracer: &'a mut Tracer, entries: Vec<(Option<&'static str>, String)>) -> Self {
+        Self {
+            tracer,
+            entries: entries.into_iter(),
+            next_key: None,
+            identifier: false,
+        }
+    }
+}
+
+impl<'de, 'a> de::MapAccess<'de> for TraceMap<'a> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        let Some((field, key)) = self.entries.next() else {
+            return Ok(None);
+        };
+        self.next_key = Some(key);
+
+        match field {
+            Some(field) => seed.deserialize(str_deserializer(field)).map(Some),
+            None => seed
+                .deserialize(SampleKey {
+                    identifier: &mut self.identifier,
+                })
+                .map(Some),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let key = self.next_key.take().unwrap_or_default();
+        let parent = std::mem::replace(&mut self.tracer.current_key, key);
+        let value = if self.identifier {
+            // the fields of a struct with flattened fields are not known, and it fails
+            // without them
+            self.tracer.flattened = true;
+            self.tracer.leaf("any");
+            Err(DeserializeError::Custom(
+                "cannot describe a struct with flattened fields".to_string(),
+            ))
+        } else {
+            seed.deserialize(&mut *self.tracer)
+        };
+        self.tracer.current_key = parent;
+        value
+    }
+}
+
+struct TraceEnum<'a> {
+    tracer: &'a mut Tracer,
+    name: &'static str,
+    variants: &'static [&'static str],
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for TraceEnum<'a> {
+    type Error = DeserializeError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(str_deserializer(self.variants[0]))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for TraceEnum<'a> {
+    type Error = DeserializeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        // unit variants are written by name
+        let rust_type = format!("{} ({})", self.name, self.variants.join(" | "));
+        self.tracer.leaf(&rust_type);
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.tracer)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.tracer.deserialize_tuple(len, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.tracer.deserialize_struct(self.name, fields, visitor)
+    }
+}
+
+fn str_deserializer(s: &'static str) -> de::value::StrDeserializer<'static, DeserializeError> {
+    s.into_deserializer()
+}
+
+/// A sample map key, parsed into whatever type the map asks for.
+struct SampleKey<'a> {
+    // set when the key is read as a field name
+    identifier: &'a mut bool,
+}
+
+impl<'de, 'a> Deserializer<'de> for SampleKey<'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_str("key")
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_char('k')
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        *self.identifier = true;
+        visitor.visit_str("key")
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let variant = variants.first().copied().unwrap_or("key");
+        visitor.visit_enum(str_deserializer(variant))
+    }
+
+    fn deserialize_i8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i8(0)
+    }
+
+    fn deserialize_i16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i16(0)
+    }
+
+    fn deserialize_i32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i32(0)
+    }
+
+    fn deserialize_i64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u8(0)
+    }
+
+    fn deserialize_u16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u16(0)
+    }
+
+    fn deserialize_u32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u32(0)
+    }
+
+    fn deserialize_u64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u64(0)
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 f32 f64 str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct ignored_any
+    }
+}
@@ -0,0 +1,209 @@
+//! Selectors that pick records or values out of a record set by path, with wildcards.
+//!
+//! A [`Selector`] is written like a displayed [`TxtPath`], e.g. `services.database` or
+//! `routes["api/v1"][0]`, where `*` matches any field and `[*]` matches any index. Selectors
+//! are matched against the paths of the record keys, read as the deserializer reads them, so
+//! they do not depend on the separators of the layout and `page_2` is only an element of
+//! `page` if the records have a length for `page`.
+//!
+//! ```rust
+//! use serde::Deserialize;
+//! use serde_txtrecord::{path, Selector, TxtRecords};
+//!
+//! #[derive(Deserialize, Debug, PartialEq)]
+//! struct Author {
+//!     name: String,
+//!     email: String,
+//! }
+//!
+//! let records: TxtRecords = [
+//!     ("title", "Rust"),
+//!     ("authors_0.name", "Steve"),
+//!     ("authors_0.email", "steve@example.com"),
+//!     ("authors_1.name", "Carol"),
+//!     ("authors_1.email", "carol@example.com"),
+//!     ("authors_len", "2"),
+//! ]
+//! .into_iter()
+//! .collect();
+//!
+//! let emails: Vec<&str> = records
+//!     .select(&"authors[*].email".parse::<Selector>().unwrap())
+//!     .into_iter()
+//!     .map(|(_, value)| value)
+//!     .collect();
+//! assert_eq!(emails, ["steve@example.com", "carol@example.com"]);
+//!
+//! let authors = records.select_as::<Author>(&"authors[*]".parse().unwrap()).unwrap();
+//! assert_eq!(authors[1].0, path!["authors", 1]);
+//! assert_eq!(authors[1].1.name, "Carol");
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::path::is_identifier;
+use crate::{DeserializeError, PathSegment, TxtPath};
+
+/// A step in a [`Selector`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectorSegment {
+    Field(String),
+    Index(usize),
+    /// `*`, any field
+    AnyField,
+    /// `[*]`, any index
+    AnyIndex,
+}
+
+impl SelectorSegment {
+    fn matches(&self, segment: &PathSegment) -> bool {
+        match (self, segment) {
+            (SelectorSegment::Field(name), PathSegment::Field(field)) => name == field,
+            (SelectorSegment::Index(index), PathSegment::Index(element)) => index == element,
+            (SelectorSegment::AnyField, PathSegment::Field(_)) => true,
+            (SelectorSegment::AnyIndex, PathSegment::Index(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A path pattern with wildcards over fields and indices, see the
+/// [module documentation](self)
+///
+/// The empty selector matches the root value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector {
+    segments: Vec<SelectorSegment>,
+}
+
+impl Selector {
+    /// Parse a selector such as `services.*.port` or `authors[*].email`
+    pub fn parse(selector: &str) -> Result<Self, DeserializeError> {
+        let invalid =
+            |reason: &str| DeserializeError::InvalidSelector(format!("{}: {}", reason, selector));
+
+        let mut segments = Vec::new();
+        let mut rest = selector;
+        while !rest.is_empty() {
+            if let Some(bracket) = rest.strip_prefix('[') {
+                let (segment, after) = parse_bracket(bracket)
+                    .ok_or_else(|| invalid("expected `[index]`, `[*]` or `[\"name\"]`"))?;
+                segments.push(segment);
+                rest = after;
+            } else {
+                if !segments.is_empty() {
+                    rest = rest
+                        .strip_prefix('.')
+                        .ok_or_else(|| invalid("expected `.` or `[`"))?;
+                }
+                let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                let segment = match &rest[..end] {
+                    "" => return Err(invalid("empty field name")),
+                    "*" => SelectorSegment::AnyField,
+                    name => SelectorSegment::Field(name.to_string()),
+                };
+                segments.push(segment);
+                rest = &rest[end..];
+            }
+        }
+        Ok(Self { segments })
+    }
+
+    pub fn segments(&self) -> &[SelectorSegment] {
+        &self.segments
+    }
+
+    /// Returns true if a path matches every segment of the selector
+    pub fn matches(&self, path: &TxtPath) -> bool {
+        path.segments().len() == self.segments.len() && self.matches_start(path)
+    }
+
+    /// Returns the part of a path that the selector matches, if the path is at or below a
+    /// match
+    pub fn matched_prefix(&self, path: &TxtPath) -> Option<TxtPath> {
+        if !self.matches_start(path) {
+            return None;
+        }
+        Some(
+            path.segments()[..self.segments.len()]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn matches_start(&self, path: &TxtPath) -> bool {
+        path.segments().len() >= self.segments.len()
+            && self
+                .segments
+                .iter()
+                .zip(path.segments())
+                .all(|(selector, segment)| selector.matches(segment))
+    }
+}
+
+/// Parse the inside of a bracket, returning the segment and the input after the bracket
+fn parse_bracket(input: &str) -> Option<(SelectorSegment, &str)> {
+    if let Some(quoted) = input.strip_prefix('"') {
+        let mut name = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '\\' => name.push(chars.next()?.1),
+                '"' => {
+                    let rest = quoted[pos + 1..].strip_prefix(']')?;
+                    return Some((SelectorSegment::Field(name), rest));
+                }
+                c => name.push(c),
+            }
+        }
+        return None;
+    }
+    let (inside, rest) = input.split_once(']')?;
+    let segment = match inside {
+        "*" => SelectorSegment::AnyIndex,
+        index => SelectorSegment::Index(index.parse().ok()?),
+    };
+    Some((segment, rest))
+}
+
+impl FromStr for Selector {
+    type Err = DeserializeError;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        Self::parse(selector)
+    }
+}
+
+impl From<TxtPath> for Selector {
+    fn from(path: TxtPath) -> Self {
+        let segments = path
+            .segments()
+            .iter()
+            .map(|segment| match segment {
+                PathSegment::Field(name) => SelectorSegment::Field(name.clone()),
+                PathSegment::Index(index) => SelectorSegment::Index(*index),
+            })
+            .collect();
+        Self { segments }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (position, segment) in self.segments.iter().enumerate() {
+            let separator = if position > 0 { "." } else { "" };
+            match segment {
+                SelectorSegment::Field(name) if is_identifier(name) => {
+                    write!(f, "{}{}", separator, name)?
+                }
+                SelectorSegment::Field(name) => write!(f, "[{:?}]", name)?,
+                SelectorSegment::Index(index) => write!(f, "[{}]", index)?,
+                SelectorSegment::AnyField => write!(f, "{}*", separator)?,
+                SelectorSegment::AnyIndex => write!(f, "[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
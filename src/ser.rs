@@ -1,14 +1,24 @@
-use serde::ser;
+use serde::ser::{self, Impossible};
 use serde::{Serialize, Serializer};
+use std::collections::HashMap;
 use std::fmt;
 
-use crate::TxtRecordConfig;
+use crate::codec::Segment;
+use crate::config::{ConfigError, KeyCase, KeyOrder, LengthUnit, OverflowAction};
+use crate::{alias, infer, TxtRecordConfig, TxtRecords};
 
 /// A serializer that converts Rust data structures to TXT record format
 pub struct TxtRecordSerializer {
     config: TxtRecordConfig,
     output: Vec<(String, String)>,
     current_key: String,
+    // Rust path of the current key, e.g. `items[0]` or `map["a.b"]`
+    current_path: String,
+    // Rust path that wrote each record key, to find keys written twice
+    written: HashMap<String, String>,
+    key_dictionary_written: bool,
+    adjustments: Vec<Adjustment>,
+    lossy: Vec<Lossy>,
 }
 
 impl TxtRecordSerializer {
@@ -21,29 +31,304 @@ impl TxtRecordSerializer {
             config,
             output: Vec::new(),
             current_key: String::new(),
+            current_path: String::new(),
+            written: HashMap::new(),
+            key_dictionary_written: false,
+            adjustments: Vec::new(),
+            lossy: Vec::new(),
         }
     }
 
-    pub fn finish(self) -> Vec<(String, String)> {
-        self.output
+    /// Returns the records in the configured [`ordering`](TxtRecordConfig::ordering)
+    pub fn finish(self) -> TxtRecords {
+        self.finish_with_adjustments().0
+    }
+
+    /// Returns the records, and what the [`overflow`](TxtRecordConfig::overflow) policy did
+    /// with the records that were too long
+    pub fn finish_with_adjustments(mut self) -> (TxtRecords, Vec<Adjustment>) {
+        self.config.ordering.sort(&mut self.output);
+        (self.output.into(), self.adjustments)
+    }
+
+    /// Returns what the overflow policy did so far
+    pub fn adjustments(&self) -> &[Adjustment] {
+        &self.adjustments
+    }
+
+    /// Returns the values serialized so far that will not deserialize to the same value
+    pub fn lossy(&self) -> &[Lossy] {
+        &self.lossy
+    }
+
+    fn report_lossy(&mut self, key: String, kind: LossyKind) {
+        self.lossy.push(Lossy { key, kind });
+    }
+
+    /// Writes a leaf value at the current key as-is.
+    fn serialize_plain(&mut self, value: String) -> Result<(), TxtRecordError> {
+        self.push_record(self.current_key.clone(), value)
     }
 
     fn push_record(&mut self, key: String, value: String) -> Result<(), TxtRecordError> {
-        let record = format!("{}={}", key, value);
-        let record_len = record.len();
+        let path = self.current_path.clone();
+        let Some(dictionary) = &self.config.key_dictionary else {
+            return self.write_record(key, value, path);
+        };
+        let encoded = dictionary.encode(&key, &self.config).map_err(|alias| {
+            TxtRecordError::AliasCollision {
+                key: key.clone(),
+                alias,
+            }
+        })?;
+        if let Some(record) = &dictionary.record {
+            let case = self.config.key_case;
+            if case.apply(&encoded) == case.apply(record) {
+                return Err(TxtRecordError::AliasCollision {
+                    key,
+                    alias: record.clone(),
+                });
+            }
+        }
+
+        self.write_key_dictionary()?;
+        self.write_record(encoded, value, path)
+    }
+
+    /// Writes the published key dictionary before the first record
+    fn write_key_dictionary(&mut self) -> Result<(), TxtRecordError> {
+        if self.key_dictionary_written {
+            return Ok(());
+        }
+        self.key_dictionary_written = true;
+        match &self.config.key_dictionary {
+            Some(
+                dictionary @ alias::KeyDictionary {
+                    record: Some(record),
+                    ..
+                },
+            ) => {
+                let (record, value) = (record.clone(), dictionary.to_record_value());
+                self.write_record(record, value, "the key dictionary".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
 
-        if record_len > self.config.record_len {
-            return Err(TxtRecordError::RecordTooLong {
+    fn write_record(
+        &mut self,
+        key: String,
+        value: String,
+        path: String,
+    ) -> Result<(), TxtRecordError> {
+        let key = match &self.config.prefix {
+            Some(prefix) if key.is_empty() => prefix.clone(),
+            Some(prefix) => format!("{}{}{}", prefix, self.config.object_separator, key),
+            None => key,
+        };
+        let key = self.config.key_case.apply(&key).into_owned();
+        if let Some(first) = self.written.get(&key) {
+            return Err(TxtRecordError::DuplicateKey {
                 key,
-                value,
-                max_len: self.config.record_len,
-                actual_len: record_len,
+                first: first.clone(),
+                second: path,
             });
         }
+        self.written.insert(key.clone(), path);
+        self.check_ascii(&key, &value)?;
+
+        match self.check_len(&key, &value) {
+            Some(error) => self.overflow(key, value, error),
+            None => {
+                self.output.push((key, value));
+                Ok(())
+            }
+        }
+    }
+
+    /// Applies the overflow policy to a record that is too long
+    fn overflow(
+        &mut self,
+        key: String,
+        value: String,
+        error: TxtRecordError,
+    ) -> Result<(), TxtRecordError> {
+        // a partial key dictionary would expand keys wrongly
+        let dictionary_key = self
+            .config
+            .key_dictionary
+            .as_ref()
+            .and_then(|dictionary| dictionary.record_key(&self.config));
+        if dictionary_key.as_ref() == Some(&key) {
+            return Err(error);
+        }
+
+        let (replacement, truncated) = match self.config.overflow.action(&key, &value) {
+            OverflowAction::Error => return Err(error),
+            OverflowAction::Skip => {
+                self.adjustments.push(Adjustment::Skipped { key, value });
+                return Ok(());
+            }
+            OverflowAction::Truncate { marker } => match self.truncate(&key, &value, &marker) {
+                Some(truncated) => (truncated, true),
+                None => return Err(error),
+            },
+            OverflowAction::Replace(replacement) => (replacement, false),
+        };
+
+        self.check_ascii(&key, &replacement)?;
+        if self.check_len(&key, &replacement).is_some() {
+            return Err(error);
+        }
+        self.output.push((key.clone(), replacement.clone()));
+        self.adjustments.push(if truncated {
+            Adjustment::Truncated {
+                key,
+                original: value,
+                value: replacement,
+            }
+        } else {
+            Adjustment::Replaced {
+                key,
+                original: value,
+                value: replacement,
+            }
+        });
+        Ok(())
+    }
+
+    /// Cuts a value so that the record fits with the marker appended, if the key and marker
+    /// leave room for it
+    fn truncate(&self, key: &str, value: &str, marker: &str) -> Option<String> {
+        let unit = self.config.length_unit;
+        let used = unit.measure(key) + unit.measure(&self.config.assignment_delimiter);
+        let available = self
+            .config
+            .record_len
+            .checked_sub(used + unit.measure(marker))?;
+        let end = match unit {
+            LengthUnit::Bytes => {
+                let mut end = available.min(value.len());
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                end
+            }
+            LengthUnit::Chars => value
+                .char_indices()
+                .nth(available)
+                .map_or(value.len(), |(end, _)| end),
+        };
+        Some(format!("{}{}", &value[..end], marker))
+    }
 
-        self.output.push((key, value));
+    fn check_ascii(&self, key: &str, value: &str) -> Result<(), TxtRecordError> {
+        if self.config.ascii_only {
+            if let Some(character) = key.chars().chain(value.chars()).find(|c| !c.is_ascii()) {
+                return Err(TxtRecordError::NonAscii {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    character,
+                });
+            }
+        }
         Ok(())
     }
+
+    /// Returns the error for a record that is longer than `record_len`
+    fn check_len(&self, key: &str, value: &str) -> Option<TxtRecordError> {
+        let delimiter = &self.config.assignment_delimiter;
+        let byte_len = key.len() + delimiter.len() + value.len();
+        let char_len = key.chars().count() + delimiter.chars().count() + value.chars().count();
+        let unit = self.config.length_unit;
+        let record_len = match unit {
+            LengthUnit::Bytes => byte_len,
+            LengthUnit::Chars => char_len,
+        };
+
+        (record_len > self.config.record_len).then(|| TxtRecordError::RecordTooLong {
+            key: key.to_string(),
+            value: value.to_string(),
+            max_len: self.config.record_len,
+            actual_len: record_len,
+            unit,
+            byte_len,
+            char_len,
+        })
+    }
+}
+
+/// A record that was changed or left out by the [`OverflowPolicy`](crate::OverflowPolicy) because it was too long
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Adjustment {
+    Truncated {
+        key: String,
+        original: String,
+        value: String,
+    },
+    Skipped {
+        key: String,
+        value: String,
+    },
+    /// The value returned by an [`OverflowPolicy::Callback`](crate::OverflowPolicy::Callback)
+    Replaced {
+        key: String,
+        original: String,
+        value: String,
+    },
+}
+
+/// A value that will not deserialize to the same value, at its key before the prefix and
+/// key dictionary are applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lossy {
+    pub key: String,
+    pub kind: LossyKind,
+}
+
+/// Why a value will not deserialize to the same value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LossyKind {
+    /// Bytes that are not valid UTF-8, written with replacement characters
+    InvalidUtf8,
+    /// An enum variant with data, written without the variant name
+    EnumVariant(&'static str),
+    /// `Some` of a value that writes no records, which reads back as `None`
+    EmptySome,
+}
+
+impl fmt::Display for Lossy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let key = if self.key.is_empty() {
+            "the root"
+        } else {
+            &self.key
+        };
+        match &self.kind {
+            LossyKind::InvalidUtf8 => write!(f, "{} has bytes that are not UTF-8", key),
+            LossyKind::EnumVariant(variant) => {
+                write!(f, "{} drops the name of the enum variant {}", key, variant)
+            }
+            LossyKind::EmptySome => {
+                write!(
+                    f,
+                    "{} is Some without records, which reads back as None",
+                    key
+                )
+            }
+        }
+    }
+}
+
+impl Adjustment {
+    /// Returns the key of the record
+    pub fn key(&self) -> &str {
+        match self {
+            Adjustment::Truncated { key, .. }
+            | Adjustment::Skipped { key, .. }
+            | Adjustment::Replaced { key, .. } => key,
+        }
+    }
 }
 
 impl Default for TxtRecordSerializer {
@@ -56,11 +341,40 @@ impl Default for TxtRecordSerializer {
 pub enum TxtRecordError {
     Custom(String),
     UnsupportedType(String),
+    InvalidMapKey(String),
+    Io(std::io::Error),
+    Config(ConfigError),
+    /// The record exceeds `record_len`, with its length in the configured unit in
+    /// `actual_len`, and in bytes and characters in `byte_len` and `char_len`
     RecordTooLong {
         key: String,
         value: String,
         max_len: usize,
         actual_len: usize,
+        unit: LengthUnit,
+        byte_len: usize,
+        char_len: usize,
+    },
+    /// The record contains a non-ASCII character while `ascii_only` is set
+    NonAscii {
+        key: String,
+        value: String,
+        character: char,
+    },
+    /// A field of the key is also an alias, or the key is the record of the key dictionary,
+    /// so the key could not be told apart when reading
+    AliasCollision {
+        key: String,
+        alias: String,
+    },
+    /// Values that will not deserialize to the same value while `reject_lossy` is set
+    Lossy(Vec<Lossy>),
+    /// Two values flatten to the same key, with the Rust paths of both, e.g. `items[0]` and
+    /// `items_0`
+    DuplicateKey {
+        key: String,
+        first: String,
+        second: String,
     },
 }
 
@@ -69,23 +383,99 @@ impl fmt::Display for TxtRecordError {
         match self {
             TxtRecordError::Custom(msg) => write!(f, "{}", msg),
             TxtRecordError::UnsupportedType(typ) => write!(f, "Unsupported type: {}", typ),
+            TxtRecordError::InvalidMapKey(msg) => write!(f, "Invalid map key: {}", msg),
+            TxtRecordError::Io(err) => write!(f, "I/O error: {}", err),
+            TxtRecordError::Config(err) => write!(f, "Invalid configuration: {}", err),
             TxtRecordError::RecordTooLong {
                 key,
                 value,
                 max_len,
                 actual_len,
+                unit,
+                byte_len,
+                char_len,
             } => {
                 write!(
                     f,
-                    "Record '{}={}' is too long: {} characters exceeds maximum of {}",
-                    key, value, actual_len, max_len
-                )
+                    "Record '{}={}' is too long: {} {} exceeds maximum of {} ({} bytes, {} characters)",
+                    key, value, actual_len, unit, max_len, byte_len, char_len
+                )?;
+
+                // some keys can never fit, no matter how short the value is
+                let key_len = actual_len - unit.measure(value);
+                if key_len >= *max_len {
+                    write!(
+                        f,
+                        " (the key and delimiter alone take {} {}, so record_len is too small for this data)",
+                        key_len, unit
+                    )?;
+                }
+                Ok(())
+            }
+            TxtRecordError::NonAscii {
+                key,
+                value,
+                character,
+            } => write!(
+                f,
+                "Record '{}={}' contains the non-ASCII character '{}' (U+{:04X})",
+                key, value, character, *character as u32
+            ),
+            TxtRecordError::AliasCollision { key, alias } => write!(
+                f,
+                "Key '{}' collides with '{}' of the key dictionary",
+                key, alias
+            ),
+            TxtRecordError::DuplicateKey { key, first, second } => write!(
+                f,
+                "Key '{}' is written by both {} and {}",
+                key,
+                display_path(first),
+                display_path(second)
+            ),
+            TxtRecordError::Lossy(lossy) => {
+                write!(f, "Lossy values: ")?;
+                for (i, lossy) in lossy.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", lossy)?;
+                }
+                Ok(())
             }
         }
     }
 }
 
-impl std::error::Error for TxtRecordError {}
+fn display_path(path: &str) -> &str {
+    if path.is_empty() {
+        "the root value"
+    } else {
+        path
+    }
+}
+
+impl std::error::Error for TxtRecordError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TxtRecordError::Io(err) => Some(err),
+            TxtRecordError::Config(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for TxtRecordError {
+    fn from(err: ConfigError) -> Self {
+        TxtRecordError::Config(err)
+    }
+}
+
+impl From<std::io::Error> for TxtRecordError {
+    fn from(err: std::io::Error) -> Self {
+        TxtRecordError::Io(err)
+    }
+}
 
 impl ser::Error for TxtRecordError {
     fn custom<T: fmt::Display>(msg: T) -> Self {
@@ -106,47 +496,55 @@ impl<'a> Serializer for &'a mut TxtRecordSerializer {
     type SerializeStructVariant = MapSerializer<'a>;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(&v.to_string())
+        self.serialize_plain(v.to_string())
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
@@ -154,12 +552,19 @@ impl<'a> Serializer for &'a mut TxtRecordSerializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        self.push_record(self.current_key.clone(), v.to_string())?;
-        Ok(())
+        if self.config.infer_types {
+            let quoted = infer::quote(v).into_owned();
+            self.serialize_plain(quoted)
+        } else {
+            self.serialize_plain(v.to_string())
+        }
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
         let s = String::from_utf8_lossy(v);
+        if std::str::from_utf8(v).is_err() {
+            self.report_lossy(self.current_key.clone(), LossyKind::InvalidUtf8);
+        }
         self.serialize_str(&s)
     }
 
@@ -167,11 +572,17 @@ impl<'a> Serializer for &'a mut TxtRecordSerializer {
         Ok(())
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        value.serialize(self)
+        let key = self.current_key.clone();
+        let len = self.output.len() + self.adjustments.len();
+        value.serialize(&mut *self)?;
+        if self.output.len() + self.adjustments.len() == len {
+            self.report_lossy(key, LossyKind::EmptySome);
+        }
+        Ok(())
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
@@ -191,27 +602,28 @@ impl<'a> Serializer for &'a mut TxtRecordSerializer {
         self.serialize_str(variant)
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
         _name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(self)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        self.report_lossy(self.current_key.clone(), LossyKind::EnumVariant(variant));
         value.serialize(self)
     }
 
@@ -235,9 +647,10 @@ impl<'a> Serializer for &'a mut TxtRecordSerializer {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        self.report_lossy(self.current_key.clone(), LossyKind::EnumVariant(variant));
         self.serialize_seq(Some(len))
     }
 
@@ -257,9 +670,10 @@ impl<'a> Serializer for &'a mut TxtRecordSerializer {
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        self.report_lossy(self.current_key.clone(), LossyKind::EnumVariant(variant));
         self.serialize_map(Some(len))
     }
 }
@@ -267,18 +681,24 @@ impl<'a> Serializer for &'a mut TxtRecordSerializer {
 pub struct SeqSerializer<'a> {
     ser: &'a mut TxtRecordSerializer,
     base_key: String,
+    base_path: String,
     index: usize,
     len: Option<usize>,
+    // elements left out because the overflow policy skipped all of their records
+    skipped: usize,
 }
 
 impl<'a> SeqSerializer<'a> {
     fn new(ser: &'a mut TxtRecordSerializer, len: Option<usize>) -> Self {
         let base_key = ser.current_key.clone();
+        let base_path = ser.current_path.clone();
         Self {
             ser,
             base_key,
+            base_path,
             index: 0,
             len,
+            skipped: 0,
         }
     }
 }
@@ -287,24 +707,43 @@ impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
     type Ok = ();
     type Error = TxtRecordError;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let key = format!(
-            "{}{}{}",
-            self.base_key, self.ser.config.array_separator, self.index
-        );
+        let key = self
+            .ser
+            .config
+            .codec()
+            .join(&self.base_key, Segment::Index(self.index));
         self.ser.current_key = key;
+        self.ser.current_path = format!("{}[{}]", self.base_path, self.index);
+        let (output, adjustments) = (self.ser.output.len(), self.ser.adjustments.len());
         value.serialize(&mut *self.ser)?;
-        self.index += 1;
+
+        // an element whose records were all skipped is left out, and the next element takes
+        // its index, so that the array has no gap below its length
+        let added = &self.ser.adjustments[adjustments..];
+        if self.ser.output.len() == output
+            && added
+                .iter()
+                .any(|adjustment| matches!(adjustment, Adjustment::Skipped { .. }))
+        {
+            for adjustment in added {
+                self.ser.written.remove(adjustment.key());
+            }
+            self.skipped += 1;
+        } else {
+            self.index += 1;
+        }
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
         // Add length metadata
-        if let Some(len) = self.len.or(Some(self.index)) {
-            let len_key = format!("{}{}", self.base_key, self.ser.config.array_len_suffix);
+        if let Some(len) = self.len.map(|len| len - self.skipped).or(Some(self.index)) {
+            let len_key = self.ser.config.codec().len_key(&self.base_key);
+            self.ser.current_path = format!("{}.len()", self.base_path);
             self.ser.push_record(len_key, len.to_string())?;
         }
         Ok(())
@@ -315,9 +754,9 @@ impl<'a> ser::SerializeTuple for SeqSerializer<'a> {
     type Ok = ();
     type Error = TxtRecordError;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         ser::SerializeSeq::serialize_element(self, value)
     }
@@ -331,9 +770,9 @@ impl<'a> ser::SerializeTupleStruct for SeqSerializer<'a> {
     type Ok = ();
     type Error = TxtRecordError;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         ser::SerializeSeq::serialize_element(self, value)
     }
@@ -347,9 +786,9 @@ impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
     type Ok = ();
     type Error = TxtRecordError;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         ser::SerializeSeq::serialize_element(self, value)
     }
@@ -362,12 +801,18 @@ impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
 pub struct MapSerializer<'a> {
     ser: &'a mut TxtRecordSerializer,
     base_key: String,
+    base_path: String,
 }
 
 impl<'a> MapSerializer<'a> {
     fn new(ser: &'a mut TxtRecordSerializer) -> Self {
         let base_key = ser.current_key.clone();
-        Self { ser, base_key }
+        let base_path = ser.current_path.clone();
+        Self {
+            ser,
+            base_key,
+            base_path,
+        }
     }
 }
 
@@ -375,29 +820,24 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
     type Ok = ();
     type Error = TxtRecordError;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let mut key_ser = TxtRecordSerializer::with_config(self.ser.config.clone());
-        key.serialize(&mut key_ser)?;
-
-        if let Some((_, key_str)) = key_ser.finish().into_iter().next() {
-            if self.base_key.is_empty() {
-                self.ser.current_key = key_str;
-            } else {
-                self.ser.current_key = format!(
-                    "{}{}{}",
-                    self.base_key, self.ser.config.object_separator, key_str
-                );
-            }
-        }
+        let key_str = key.serialize(MapKeySerializer)?;
+        self.ser.current_path = format!("{}[{:?}]", self.base_path, key_str);
+
+        self.ser.current_key = self
+            .ser
+            .config
+            .codec()
+            .join(&self.base_key, Segment::MapKey(&key_str));
         Ok(())
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut *self.ser)
     }
@@ -411,22 +851,20 @@ impl<'a> ser::SerializeStruct for MapSerializer<'a> {
     type Ok = ();
     type Error = TxtRecordError;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        if self.base_key.is_empty() {
-            self.ser.current_key = key.to_string();
+        self.ser.current_path = if self.base_path.is_empty() {
+            key.to_string()
         } else {
-            self.ser.current_key = format!(
-                "{}{}{}",
-                self.base_key, self.ser.config.object_separator, key
-            );
-        }
+            format!("{}.{}", self.base_path, key)
+        };
+        self.ser.current_key = self
+            .ser
+            .config
+            .codec()
+            .join(&self.base_key, Segment::Field(key));
         value.serialize(&mut *self.ser)
     }
 
@@ -439,13 +877,9 @@ impl<'a> ser::SerializeStructVariant for MapSerializer<'a> {
     type Ok = ();
     type Error = TxtRecordError;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         ser::SerializeStruct::serialize_field(self, key, value)
     }
@@ -455,8 +889,209 @@ impl<'a> ser::SerializeStructVariant for MapSerializer<'a> {
     }
 }
 
+/// Serializes a map key into the string used as its key segment.
+///
+/// Only scalar keys can be flattened into a record key; compound keys such as
+/// tuples or structs are rejected, since they would not produce a single segment.
+struct MapKeySerializer;
+
+impl MapKeySerializer {
+    fn unsupported(kind: &str) -> TxtRecordError {
+        TxtRecordError::InvalidMapKey(format!("{} cannot be used as a map key", kind))
+    }
+}
+
+impl Serializer for MapKeySerializer {
+    type Ok = String;
+    type Error = TxtRecordError;
+
+    type SerializeSeq = Impossible<String, TxtRecordError>;
+    type SerializeTuple = Impossible<String, TxtRecordError>;
+    type SerializeTupleStruct = Impossible<String, TxtRecordError>;
+    type SerializeTupleVariant = Impossible<String, TxtRecordError>;
+    type SerializeMap = Impossible<String, TxtRecordError>;
+    type SerializeStruct = Impossible<String, TxtRecordError>;
+    type SerializeStructVariant = Impossible<String, TxtRecordError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        // a lossy key could silently merge with another key
+        std::str::from_utf8(v).map(str::to_string).map_err(|_| {
+            TxtRecordError::InvalidMapKey(format!("bytes {:?} are not valid UTF-8", v))
+        })
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(Self::unsupported("None"))
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(Self::unsupported("unit"))
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Err(Self::unsupported(&format!("unit struct {}", name)))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(Self::unsupported(&format!(
+            "newtype variant {}::{}",
+            name, variant
+        )))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(Self::unsupported("sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(Self::unsupported("tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(Self::unsupported(&format!("tuple struct {}", name)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(Self::unsupported(&format!(
+            "tuple variant {}::{}",
+            name, variant
+        )))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(Self::unsupported("map"))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(Self::unsupported(&format!("struct {}", name)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(Self::unsupported(&format!(
+            "struct variant {}::{}",
+            name, variant
+        )))
+    }
+}
+
 /// Serialize a value to TXT record format
-pub fn to_txt_records<T>(value: &T) -> Result<Vec<(String, String)>, TxtRecordError>
+pub fn to_txt_records<T>(value: &T) -> Result<TxtRecords, TxtRecordError>
 where
     T: Serialize,
 {
@@ -467,11 +1102,62 @@ where
 pub fn to_txt_records_with_config<T>(
     value: &T,
     config: TxtRecordConfig,
-) -> Result<Vec<(String, String)>, TxtRecordError>
+) -> Result<TxtRecords, TxtRecordError>
 where
     T: Serialize,
 {
+    to_txt_records_with_adjustments(value, config).map(|(records, _)| records)
+}
+
+/// Serialize a value with custom configuration, returning what the
+/// [`overflow`](TxtRecordConfig::overflow) policy did with the records that were too long
+pub fn to_txt_records_with_adjustments<T>(
+    value: &T,
+    config: TxtRecordConfig,
+) -> Result<(TxtRecords, Vec<Adjustment>), TxtRecordError>
+where
+    T: Serialize,
+{
+    config.validate()?;
+    let config = derive_key_dictionary(value, config)?;
     let mut serializer = TxtRecordSerializer::with_config(config);
     value.serialize(&mut serializer)?;
-    Ok(serializer.finish())
+    serializer.write_key_dictionary()?;
+    if serializer.config.reject_lossy && !serializer.lossy.is_empty() {
+        return Err(TxtRecordError::Lossy(serializer.lossy));
+    }
+    Ok(serializer.finish_with_adjustments())
+}
+
+/// Derive the aliases of a derived key dictionary from the unaliased keys of the value
+fn derive_key_dictionary<T>(
+    value: &T,
+    mut config: TxtRecordConfig,
+) -> Result<TxtRecordConfig, TxtRecordError>
+where
+    T: Serialize,
+{
+    if !config.key_dictionary.as_ref().is_some_and(|d| d.derive) {
+        return Ok(config);
+    }
+    let mut serializer = TxtRecordSerializer::with_config(TxtRecordConfig {
+        record_len: usize::MAX,
+        key_case: KeyCase::Preserve,
+        prefix: None,
+        ordering: KeyOrder::Preserve,
+        ascii_only: false,
+        key_dictionary: None,
+        ..config.clone()
+    });
+    value.serialize(&mut serializer)?;
+
+    let aliases = alias::derive_aliases(
+        serializer.output.iter().map(|(key, _)| key.as_str()),
+        &config,
+    );
+    if let Some(dictionary) = &mut config.key_dictionary {
+        dictionary.aliases = aliases;
+        dictionary.derive = false;
+    }
+    Ok(config)
 }
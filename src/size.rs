@@ -0,0 +1,183 @@
+//! Size reports that show which records and fields take up the space of a record set.
+//!
+//! [`size_report`] serializes a value without the [`record_len`](TxtRecordConfig::record_len)
+//! limit and measures every record against it, so that all violations are listed at once.
+//!
+//! ```rust
+//! use serde::Serialize;
+//! use serde_txtrecord::{size_report, TxtRecordConfig};
+//!
+//! #[derive(Serialize)]
+//! struct Service {
+//!     name: String,
+//!     notes: Vec<String>,
+//! }
+//!
+//! let service = Service {
+//!     name: "api".to_string(),
+//!     notes: vec!["x".repeat(300), "short".to_string()],
+//! };
+//!
+//! let mut report = size_report(&service, &TxtRecordConfig::default()).unwrap();
+//! assert_eq!(report.violations().count(), 1);
+//!
+//! report.sort_by_size();
+//! assert_eq!(report.records[0].key, "notes_0");
+//! assert_eq!(report.records[0].headroom, -53); // 308 bytes against 255
+//! assert_eq!(report.subtree("notes").unwrap().records, 3);
+//! ```
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::{to_txt_records_with_config, TxtRecordConfig, TxtRecordError};
+
+/// Sizes of the records of a value, measured in the configured
+/// [`length_unit`](TxtRecordConfig::length_unit)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeReport {
+    /// Every record, in serialization order until sorted
+    pub records: Vec<RecordSize>,
+    /// Totals of every object and array, such as `authors` or `authors_0`, in key order
+    /// until sorted
+    pub subtrees: Vec<SubtreeSize>,
+    /// The limit the records are measured against
+    pub max_len: usize,
+    /// Total length of all records
+    pub total_len: usize,
+}
+
+/// The size of a single `key=value` record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordSize {
+    pub key: String,
+    pub key_len: usize,
+    pub value_len: usize,
+    /// Length of the whole record, including the assignment delimiter
+    pub len: usize,
+    /// Room left before the limit, negative if the record is too long
+    pub headroom: i64,
+}
+
+/// The total size of the records below an object or array
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtreeSize {
+    pub path: String,
+    /// Number of records in the subtree, including array length records
+    pub records: usize,
+    /// Total length of those records
+    pub len: usize,
+}
+
+impl RecordSize {
+    /// Returns true if the record is longer than the limit
+    pub fn is_violation(&self) -> bool {
+        self.headroom < 0
+    }
+}
+
+impl SizeReport {
+    /// Returns the records that are longer than the limit
+    pub fn violations(&self) -> impl Iterator<Item = &RecordSize> {
+        self.records.iter().filter(|record| record.is_violation())
+    }
+
+    /// Returns the totals of an object or array
+    pub fn subtree(&self, path: &str) -> Option<&SubtreeSize> {
+        self.subtrees.iter().find(|subtree| subtree.path == path)
+    }
+
+    /// Sort records and subtrees from the largest to the smallest, and by key for equal sizes
+    pub fn sort_by_size(&mut self) {
+        self.records
+            .sort_by(|a, b| b.len.cmp(&a.len).then_with(|| a.key.cmp(&b.key)));
+        self.subtrees
+            .sort_by(|a, b| b.len.cmp(&a.len).then_with(|| a.path.cmp(&b.path)));
+    }
+}
+
+/// Measure the records of a value against the configured `record_len`
+///
+/// Records that are too long do not fail serialization, and are reported with a negative
+/// headroom instead.
+pub fn size_report<T>(value: &T, config: &TxtRecordConfig) -> Result<SizeReport, TxtRecordError>
+where
+    T: Serialize,
+{
+    config.validate()?;
+    let unlimited = TxtRecordConfig {
+        record_len: usize::MAX,
+        ascii_only: false,
+        ..config.clone()
+    };
+    let records = to_txt_records_with_config(value, unlimited)?;
+
+    let unit = config.length_unit;
+    let delimiter_len = unit.measure(&config.assignment_delimiter);
+    let sizes: Vec<RecordSize> = records
+        .iter()
+        .map(|(key, value)| {
+            let key_len = unit.measure(key);
+            let value_len = unit.measure(value);
+            let len = key_len + delimiter_len + value_len;
+            let headroom = (config.record_len as i128 - len as i128)
+                .clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+            RecordSize {
+                key: key.clone(),
+                key_len,
+                value_len,
+                len,
+                headroom,
+            }
+        })
+        .collect();
+
+    let namespace = config.namespace();
+    let exists = |key: &str| records.contains_key(&format!("{}{}", namespace, key));
+
+    let mut subtrees: BTreeMap<String, SubtreeSize> = BTreeMap::new();
+    for size in &sizes {
+        for path in subtree_paths(&size.key, &namespace, &exists, config) {
+            let subtree = subtrees.entry(path.clone()).or_insert(SubtreeSize {
+                path,
+                records: 0,
+                len: 0,
+            });
+            subtree.records += 1;
+            subtree.len += size.len;
+        }
+    }
+
+    Ok(SizeReport {
+        total_len: sizes.iter().map(|size| size.len).sum(),
+        records: sizes,
+        subtrees: subtrees.into_values().collect(),
+        max_len: config.record_len,
+    })
+}
+
+/// Returns the objects and arrays that contain a record, e.g. `authors` and `authors_0` for
+/// `authors_0.name`, including the prefix.
+fn subtree_paths(
+    key: &str,
+    namespace: &str,
+    exists: &dyn Fn(&str) -> bool,
+    config: &TxtRecordConfig,
+) -> Vec<String> {
+    let Some(rest) = key.strip_prefix(namespace) else {
+        return Vec::new();
+    };
+    let prefix = config
+        .prefix
+        .as_ref()
+        .map(|prefix| config.key_case.apply(prefix).into_owned());
+    prefix
+        .into_iter()
+        .chain(
+            config
+                .parent_keys(rest, exists)
+                .into_iter()
+                .map(|parent| format!("{}{}", namespace, parent)),
+        )
+        .collect()
+}
@@ -0,0 +1,242 @@
+//! A line-oriented text format for TXT records.
+//!
+//! Each record is written on its own line as `key=value`, where the first `=` splits the
+//! key from the value. The delimiter can be changed with
+//! [`TxtRecordConfig::assignment_delimiter`]. When reading, blank lines and lines starting
+//! with `#` are ignored.
+//!
+//! Newlines, carriage returns, tabs and backslashes are escaped as `\n`, `\r`, `\t` and `\\`.
+//! Within keys, the delimiter and a leading `#` are escaped with a backslash as well, so that
+//! any record can be written and read back.
+//!
+//! ```rust
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Motd {
+//!     title: String,
+//!     lines: Vec<String>,
+//! }
+//!
+//! let motd = Motd {
+//!     title: "Welcome".to_string(),
+//!     lines: vec!["first\nsecond".to_string()],
+//! };
+//!
+//! let text = serde_txtrecord::to_string(&motd).unwrap();
+//! assert_eq!(text, "title=Welcome\nlines_0=first\\nsecond\nlines_len=1\n");
+//!
+//! let parsed: Motd = serde_txtrecord::from_str(&text).unwrap();
+//! assert_eq!(motd, parsed);
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::io;
+
+use crate::{
+    from_txt_records_with_config, to_txt_records_with_config, DeserializeError, TxtRecordConfig,
+    TxtRecordError, TxtRecords,
+};
+
+/// Serialize a value to the line-oriented text format
+pub fn to_string<T>(value: &T) -> Result<String, TxtRecordError>
+where
+    T: Serialize,
+{
+    to_string_with_config(value, TxtRecordConfig::default())
+}
+
+/// Serialize a value to the line-oriented text format with custom configuration
+pub fn to_string_with_config<T>(
+    value: &T,
+    config: TxtRecordConfig,
+) -> Result<String, TxtRecordError>
+where
+    T: Serialize,
+{
+    let mut output = Vec::new();
+    to_writer_with_config(&mut output, value, config)?;
+    // only valid UTF-8 is ever written
+    Ok(String::from_utf8(output).expect("text format output is valid UTF-8"))
+}
+
+/// Serialize a value to the line-oriented text format, writing it to `writer`
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<(), TxtRecordError>
+where
+    W: io::Write,
+    T: Serialize,
+{
+    to_writer_with_config(writer, value, TxtRecordConfig::default())
+}
+
+/// Serialize a value to the line-oriented text format with custom configuration, writing it
+/// to `writer`
+pub fn to_writer_with_config<W, T>(
+    mut writer: W,
+    value: &T,
+    config: TxtRecordConfig,
+) -> Result<(), TxtRecordError>
+where
+    W: io::Write,
+    T: Serialize,
+{
+    let delimiter = config.assignment_delimiter.clone();
+    let records = to_txt_records_with_config(value, config)?;
+    write_records(&mut writer, &records, &delimiter)?;
+    Ok(())
+}
+
+/// Write records to `writer` in the line-oriented text format, one record per line
+pub fn write_records<W>(
+    mut writer: W,
+    records: &[(String, String)],
+    delimiter: &str,
+) -> io::Result<()>
+where
+    W: io::Write,
+{
+    for (key, value) in records {
+        writeln!(
+            writer,
+            "{}{}{}",
+            escape_key(key, delimiter),
+            delimiter,
+            escape(value)
+        )?;
+    }
+    writer.flush()
+}
+
+/// Deserialize a value from the line-oriented text format
+pub fn from_str<T>(input: &str) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    from_str_with_config(input, TxtRecordConfig::default())
+}
+
+/// Deserialize a value from the line-oriented text format with custom configuration
+pub fn from_str_with_config<T>(input: &str, config: TxtRecordConfig) -> Result<T, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let records = parse_records(input, &config.assignment_delimiter)?;
+    from_txt_records_with_config(records, config)
+}
+
+/// Deserialize a value from the line-oriented text format, reading it from `reader`
+pub fn from_reader<R, T>(reader: R) -> Result<T, DeserializeError>
+where
+    R: io::Read,
+    T: for<'de> Deserialize<'de>,
+{
+    from_reader_with_config(reader, TxtRecordConfig::default())
+}
+
+/// Deserialize a value from the line-oriented text format with custom configuration, reading
+/// it from `reader`
+pub fn from_reader_with_config<R, T>(
+    mut reader: R,
+    config: TxtRecordConfig,
+) -> Result<T, DeserializeError>
+where
+    R: io::Read,
+    T: for<'de> Deserialize<'de>,
+{
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    from_str_with_config(&input, config)
+}
+
+/// Parse records from the line-oriented text format, skipping blank lines and comments
+///
+/// A later line replaces an earlier line with the same key.
+pub fn parse_records(input: &str, delimiter: &str) -> Result<TxtRecords, DeserializeError> {
+    let mut records = TxtRecords::new();
+    for (number, line) in input.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = split_line(line, delimiter).ok_or_else(|| {
+            DeserializeError::InvalidFormat(format!(
+                "line {}: missing '{}' between key and value",
+                number + 1,
+                delimiter
+            ))
+        })?;
+        let key = unescape(key).map_err(|msg| {
+            DeserializeError::InvalidFormat(format!("line {}: {}", number + 1, msg))
+        })?;
+        let value = unescape(value).map_err(|msg| {
+            DeserializeError::InvalidFormat(format!("line {}: {}", number + 1, msg))
+        })?;
+        records.insert(key, value);
+    }
+    Ok(records)
+}
+
+/// Splits a line at the first unescaped delimiter.
+fn split_line<'a>(line: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    if delimiter.is_empty() {
+        return None;
+    }
+
+    let mut chars = line.char_indices();
+    while let Some((pos, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if line[pos..].starts_with(delimiter) {
+            return Some((&line[..pos], &line[pos + delimiter.len()..]));
+        }
+    }
+    None
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_key(key: &str, delimiter: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for (pos, c) in key.char_indices() {
+        let is_comment = c == '#' && key[..pos].trim().is_empty();
+        let is_delimiter = !delimiter.is_empty() && key[pos..].starts_with(delimiter);
+        if is_comment || is_delimiter {
+            escaped.push('\\');
+        }
+        escaped.push_str(&escape(c.encode_utf8(&mut [0; 4])));
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some(c) => unescaped.push(c),
+            None => return Err("unterminated escape sequence at end of line".to_string()),
+        }
+    }
+    Ok(unescaped)
+}
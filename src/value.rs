@@ -0,0 +1,466 @@
+//! A dynamically typed tree for records without a concrete Rust type.
+//!
+//! [`TxtValue`] is to TXT records what `serde_json::Value` is to JSON: it can hold any
+//! record set, be indexed by path and be converted back to records.
+//!
+//! ```rust
+//! use serde_txtrecord::TxtValue;
+//!
+//! let records = vec![
+//!     ("services.cache.host".to_string(), "localhost".to_string()),
+//!     ("services.cache.port".to_string(), "6379".to_string()),
+//!     ("tags_0".to_string(), "fast".to_string()),
+//!     ("tags_len".to_string(), "1".to_string()),
+//! ];
+//!
+//! let value = TxtValue::from_records(records).unwrap();
+//! assert_eq!(value["services"]["cache"]["port"].as_u64(), Some(6379));
+//! assert_eq!(value["tags"][0].as_str(), Some("fast"));
+//! assert!(value["services"]["missing"].is_null());
+//! ```
+//!
+//! Leaves read from records are strings, unless
+//! [`TxtRecordConfig::infer_types`](crate::TxtRecordConfig::infer_types) is enabled. The
+//! numeric and boolean accessors parse strings, so they work either way.
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops;
+
+use crate::{
+    from_txt_records_with_config, to_txt_records_with_config, DeserializeError, TxtRecordConfig,
+    TxtRecordError, TxtRecords,
+};
+
+/// Any value that can be represented by TXT records
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum TxtValue {
+    /// A missing value, written as no record at all
+    #[default]
+    Null,
+    Bool(bool),
+    Integer(i128),
+    Float(f64),
+    String(String),
+    Array(Vec<TxtValue>),
+    Object(BTreeMap<String, TxtValue>),
+}
+
+static NULL: TxtValue = TxtValue::Null;
+
+impl TxtValue {
+    /// Build a value from TXT records
+    pub fn from_records(records: impl Into<TxtRecords>) -> Result<Self, DeserializeError> {
+        Self::from_records_with_config(records, TxtRecordConfig::default())
+    }
+
+    /// Build a value from TXT records with custom configuration
+    pub fn from_records_with_config(
+        records: impl Into<TxtRecords>,
+        config: TxtRecordConfig,
+    ) -> Result<Self, DeserializeError> {
+        let records = records.into();
+        if records.is_empty() {
+            return Ok(TxtValue::Object(BTreeMap::new()));
+        }
+        from_txt_records_with_config(records, config)
+    }
+
+    /// Flatten the value into TXT records
+    pub fn to_records(&self) -> Result<TxtRecords, TxtRecordError> {
+        self.to_records_with_config(TxtRecordConfig::default())
+    }
+
+    /// Flatten the value into TXT records with custom configuration
+    ///
+    /// The root must be an object or an array, since other values would be written to a
+    /// record with an empty key, unless a [`prefix`](TxtRecordConfig::prefix) names it.
+    pub fn to_records_with_config(
+        &self,
+        config: TxtRecordConfig,
+    ) -> Result<TxtRecords, TxtRecordError> {
+        let kind = match self {
+            TxtValue::Object(_) | TxtValue::Array(_) => None,
+            TxtValue::Null => Some("null"),
+            TxtValue::Bool(_) => Some("a boolean"),
+            TxtValue::Integer(_) => Some("an integer"),
+            TxtValue::Float(_) => Some("a float"),
+            TxtValue::String(_) => Some("a string"),
+        };
+        match kind {
+            Some(kind) if config.prefix.is_none() => Err(TxtRecordError::UnsupportedType(format!(
+                "{} at the root has no key without a prefix",
+                kind
+            ))),
+            _ => to_txt_records_with_config(self, config),
+        }
+    }
+
+    /// Returns the member of an object, or `None` if this is not an object or the member is missing
+    pub fn get(&self, key: &str) -> Option<&TxtValue> {
+        match self {
+            TxtValue::Object(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// Returns the element of an array, or `None` if this is not an array or out of bounds
+    pub fn get_index(&self, index: usize) -> Option<&TxtValue> {
+        match self {
+            TxtValue::Array(items) => items.get(index),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, TxtValue::Null)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            TxtValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the boolean value, parsing strings such as `"true"`
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            TxtValue::Bool(b) => Some(*b),
+            TxtValue::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an `i64`, parsing strings such as `"-42"`
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            TxtValue::Integer(n) => i64::try_from(*n).ok(),
+            TxtValue::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a `u64`, parsing strings such as `"42"`
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            TxtValue::Integer(n) => u64::try_from(*n).ok(),
+            TxtValue::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an `f64`, parsing strings such as `"0.5"`
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            TxtValue::Integer(n) => Some(*n as f64),
+            TxtValue::Float(n) => Some(*n),
+            TxtValue::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<TxtValue>> {
+        match self {
+            TxtValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&BTreeMap<String, TxtValue>> {
+        match self {
+            TxtValue::Object(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    fn fmt_tree(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        let children: Vec<(String, &TxtValue)> = match self {
+            TxtValue::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| (format!("[{}]", index), item))
+                .collect(),
+            TxtValue::Object(map) => map.iter().map(|(key, item)| (key.clone(), item)).collect(),
+            _ => return Ok(()),
+        };
+
+        for (label, child) in children {
+            write!(f, "{:indent$}{}:", "", label, indent = indent)?;
+            match child {
+                TxtValue::Array(items) if !items.is_empty() => {
+                    writeln!(f)?;
+                    child.fmt_tree(f, indent + 2)?;
+                }
+                TxtValue::Object(map) if !map.is_empty() => {
+                    writeln!(f)?;
+                    child.fmt_tree(f, indent + 2)?;
+                }
+                scalar => writeln!(f, " {}", scalar)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders scalars as their record value, and arrays and objects as an indented tree
+impl fmt::Display for TxtValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxtValue::Null => write!(f, "null"),
+            TxtValue::Bool(b) => write!(f, "{}", b),
+            TxtValue::Integer(n) => write!(f, "{}", n),
+            TxtValue::Float(n) => write!(f, "{}", n),
+            TxtValue::String(s) => write!(f, "{}", s),
+            TxtValue::Array(items) if items.is_empty() => write!(f, "[]"),
+            TxtValue::Object(map) if map.is_empty() => write!(f, "{{}}"),
+            _ => self.fmt_tree(f, 0),
+        }
+    }
+}
+
+impl ops::Index<&str> for TxtValue {
+    type Output = TxtValue;
+
+    /// Returns the member of an object, or `Null` if there is no such member
+    fn index(&self, key: &str) -> &TxtValue {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+impl ops::Index<usize> for TxtValue {
+    type Output = TxtValue;
+
+    /// Returns the element of an array, or `Null` if there is no such element
+    fn index(&self, index: usize) -> &TxtValue {
+        self.get_index(index).unwrap_or(&NULL)
+    }
+}
+
+impl ops::IndexMut<&str> for TxtValue {
+    /// Returns the member of an object, inserting `Null` if it is missing
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is neither an object nor `Null`.
+    fn index_mut(&mut self, key: &str) -> &mut TxtValue {
+        if self.is_null() {
+            *self = TxtValue::Object(BTreeMap::new());
+        }
+        match self {
+            TxtValue::Object(map) => map.entry(key.to_string()).or_default(),
+            other => panic!("cannot index into {:?} with key '{}'", other, key),
+        }
+    }
+}
+
+impl ops::IndexMut<usize> for TxtValue {
+    /// Returns the element of an array
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not an array or the index is out of bounds.
+    fn index_mut(&mut self, index: usize) -> &mut TxtValue {
+        match self {
+            TxtValue::Array(items) => {
+                let len = items.len();
+                items.get_mut(index).unwrap_or_else(|| {
+                    panic!("index {} out of bounds for array of length {}", index, len)
+                })
+            }
+            other => panic!("cannot index into {:?} with index {}", other, index),
+        }
+    }
+}
+
+impl From<bool> for TxtValue {
+    fn from(b: bool) -> Self {
+        TxtValue::Bool(b)
+    }
+}
+
+macro_rules! from_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for TxtValue {
+                fn from(n: $ty) -> Self {
+                    TxtValue::Integer(n as i128)
+                }
+            }
+        )*
+    };
+}
+
+from_integer!(i8, i16, i32, i64, i128, u8, u16, u32, u64, usize, isize);
+
+impl From<f64> for TxtValue {
+    fn from(n: f64) -> Self {
+        TxtValue::Float(n)
+    }
+}
+
+impl From<&str> for TxtValue {
+    fn from(s: &str) -> Self {
+        TxtValue::String(s.to_string())
+    }
+}
+
+impl From<String> for TxtValue {
+    fn from(s: String) -> Self {
+        TxtValue::String(s)
+    }
+}
+
+impl<T: Into<TxtValue>> From<Vec<T>> for TxtValue {
+    fn from(items: Vec<T>) -> Self {
+        TxtValue::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<TxtValue>> From<Option<T>> for TxtValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(TxtValue::Null, Into::into)
+    }
+}
+
+impl<K: Into<String>, V: Into<TxtValue>> FromIterator<(K, V)> for TxtValue {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        TxtValue::Object(
+            iter.into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+impl Serialize for TxtValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            TxtValue::Null => serializer.serialize_unit(),
+            TxtValue::Bool(b) => serializer.serialize_bool(*b),
+            TxtValue::Integer(n) => {
+                if let Ok(n) = i64::try_from(*n) {
+                    serializer.serialize_i64(n)
+                } else if let Ok(n) = u64::try_from(*n) {
+                    serializer.serialize_u64(n)
+                } else {
+                    serializer.serialize_i128(*n)
+                }
+            }
+            TxtValue::Float(n) => serializer.serialize_f64(*n),
+            TxtValue::String(s) => serializer.serialize_str(s),
+            TxtValue::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            TxtValue::Object(map) => {
+                let mut object = serializer.serialize_map(Some(map.len()))?;
+                for (key, value) in map {
+                    object.serialize_entry(key, value)?;
+                }
+                object.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TxtValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(TxtValueVisitor)
+    }
+}
+
+struct TxtValueVisitor;
+
+impl<'de> Visitor<'de> for TxtValueVisitor {
+    type Value = TxtValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "any value representable by TXT records")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<TxtValue, E> {
+        Ok(TxtValue::Bool(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<TxtValue, E> {
+        Ok(TxtValue::Integer(n.into()))
+    }
+
+    fn visit_i128<E>(self, n: i128) -> Result<TxtValue, E> {
+        Ok(TxtValue::Integer(n))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<TxtValue, E> {
+        Ok(TxtValue::Integer(n.into()))
+    }
+
+    fn visit_u128<E>(self, n: u128) -> Result<TxtValue, E>
+    where
+        E: de::Error,
+    {
+        i128::try_from(n)
+            .map(TxtValue::Integer)
+            .map_err(|_| E::custom(format!("integer {} is out of range", n)))
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<TxtValue, E> {
+        Ok(TxtValue::Float(n))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<TxtValue, E> {
+        Ok(TxtValue::String(s.to_string()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<TxtValue, E> {
+        Ok(TxtValue::String(s))
+    }
+
+    fn visit_unit<E>(self) -> Result<TxtValue, E> {
+        Ok(TxtValue::Null)
+    }
+
+    fn visit_none<E>(self) -> Result<TxtValue, E> {
+        Ok(TxtValue::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<TxtValue, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<TxtValue, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(TxtValue::Array(items))
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<TxtValue, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut map = BTreeMap::new();
+        while let Some((key, value)) = access.next_entry::<String, TxtValue>()? {
+            map.insert(key, value);
+        }
+        Ok(TxtValue::Object(map))
+    }
+}
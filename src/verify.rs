@@ -0,0 +1,137 @@
+//! Checks that values survive the record format, for test suites of configuration types.
+//!
+//! Some values serialize without error but read back differently: enum variants with data
+//! lose their name, bytes that are not UTF-8 get replacement characters, and `Some` of a
+//! value without records reads back as `None`. [`check_lossy`] lists those values with
+//! their keys, and [`verify_roundtrip`] serializes, deserializes and compares a value.
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_txtrecord::{check_lossy, verify_roundtrip, TxtRecordConfig};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! enum Backend {
+//!     Memory,
+//!     Redis(String),
+//! }
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Cache {
+//!     backend: Backend,
+//!     marker: Option<()>,
+//! }
+//!
+//! let config = TxtRecordConfig::default();
+//! let safe = Cache { backend: Backend::Memory, marker: None };
+//! verify_roundtrip(&safe, &config).unwrap();
+//!
+//! let lossy = Cache { backend: Backend::Redis("redis://cache".to_string()), marker: Some(()) };
+//! let found: Vec<String> = check_lossy(&lossy, &config).unwrap().iter().map(|l| l.to_string()).collect();
+//! assert_eq!(found, vec![
+//!     "backend drops the name of the enum variant Redis",
+//!     "marker is Some without records, which reads back as None",
+//! ]);
+//! assert!(verify_roundtrip(&lossy, &config).is_err());
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::ser::Lossy;
+use crate::{
+    from_txt_records_with_config, to_txt_records_with_config, DeserializeError, TxtRecordConfig,
+    TxtRecordError, TxtRecordSerializer,
+};
+
+/// Why a value did not survive serialization and deserialization
+#[derive(Debug)]
+pub enum RoundtripError {
+    Serialize(TxtRecordError),
+    /// The records could not be read back
+    Deserialize {
+        error: DeserializeError,
+        lossy: Vec<Lossy>,
+    },
+    /// The records were read back as a different value, both written with `{:?}`
+    Mismatch {
+        expected: String,
+        actual: String,
+        lossy: Vec<Lossy>,
+    },
+}
+
+impl fmt::Display for RoundtripError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lossy = match self {
+            RoundtripError::Serialize(err) => return write!(f, "Serialization failed: {}", err),
+            RoundtripError::Deserialize { error, lossy } => {
+                write!(f, "Deserialization failed: {}", error)?;
+                lossy
+            }
+            RoundtripError::Mismatch {
+                expected,
+                actual,
+                lossy,
+            } => {
+                write!(f, "Value changed: expected {}, got {}", expected, actual)?;
+                lossy
+            }
+        };
+        for lossy in lossy {
+            write!(f, "; {}", lossy)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RoundtripError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RoundtripError::Serialize(err) => Some(err),
+            RoundtripError::Deserialize { error, .. } => Some(error),
+            RoundtripError::Mismatch { .. } => None,
+        }
+    }
+}
+
+impl From<TxtRecordError> for RoundtripError {
+    fn from(err: TxtRecordError) -> Self {
+        RoundtripError::Serialize(err)
+    }
+}
+
+/// Find every value that will not deserialize to the same value
+///
+/// Records are not limited in length while checking, and `reject_lossy` is ignored.
+pub fn check_lossy<T>(value: &T, config: &TxtRecordConfig) -> Result<Vec<Lossy>, TxtRecordError>
+where
+    T: Serialize,
+{
+    config.validate()?;
+    let mut serializer = TxtRecordSerializer::with_config(TxtRecordConfig {
+        record_len: usize::MAX,
+        ascii_only: false,
+        key_dictionary: None,
+        ..config.clone()
+    });
+    value.serialize(&mut serializer)?;
+    Ok(serializer.lossy().to_vec())
+}
+
+/// Serialize a value, deserialize the records and check that the result is equal
+pub fn verify_roundtrip<T>(value: &T, config: &TxtRecordConfig) -> Result<(), RoundtripError>
+where
+    T: Serialize + for<'de> Deserialize<'de> + PartialEq + fmt::Debug,
+{
+    let records = to_txt_records_with_config(value, config.clone())?;
+    let lossy = check_lossy(value, config)?;
+    match from_txt_records_with_config::<T>(records, config.clone()) {
+        Ok(decoded) if decoded == *value => Ok(()),
+        Ok(decoded) => Err(RoundtripError::Mismatch {
+            expected: format!("{:?}", value),
+            actual: format!("{:?}", decoded),
+            lossy,
+        }),
+        Err(error) => Err(RoundtripError::Deserialize { error, lossy }),
+    }
+}